@@ -35,7 +35,7 @@ fn print_message(msg: &str) -> Result<i32, IoError> {
 }
 
 #[cfg(not(windows))]
-fn print_message(msg: str) -> Result<(), IoError> {
+fn print_message(msg: &str) -> Result<(), IoError> {
     println!("Not Windows{}", msg);
     Ok(())
 }
@@ -0,0 +1,339 @@
+//! Cursor math for looping sound output buffers.
+//!
+//! A sound card plays a ring buffer over and over, reporting a play cursor
+//! and a write cursor in bytes. Everything between the two is already
+//! committed to the hardware. `AudioRingBuffer` tracks how many samples the
+//! platform has written in total and works out where the next write starts,
+//! how long it is and how it splits at the end of the ring, without touching
+//! any device. `SimulatedAudioDevice` plays such a ring in real time against
+//! a `Clock` so the latency logic can be tested without a sound card.
+//!
+//! `AudioSyncMarkers` keeps the cursors of the last few frames and draws
+//! them over the back buffer, showing where in the ring the card is playing
+//! and writing and how far ahead of it the platform writes.
+
+use crate::render;
+use crate::timing::Clock;
+use crate::GameScreenBuffer;
+use core::ops::Range;
+
+/// Bytes from `from` forward to `to` in a ring of `size` bytes.
+fn ring_distance(from: u32, to: u32, size: u32) -> u32 {
+    if to >= from {
+        to - from
+    } else {
+        size - from + to
+    }
+}
+
+/// What to write into the ring this frame, see `AudioRingBuffer::next_write`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioWrite {
+    /// Byte offset into the ring the write starts at.
+    pub lock_offset: u32,
+    pub bytes_to_write: u32,
+    pub sample_count: u32,
+    /// Where the write ends once it is complete, `latency_sample_count`
+    /// samples past the write cursor.
+    pub target_cursor: u32,
+    /// Samples that were played before they could be written. The write
+    /// starts at the write cursor instead and leaves them out.
+    pub skipped_samples: u32,
+}
+
+/// A ring buffer of `buffer_size` bytes the sound card loops over.
+#[derive(Debug, Clone)]
+pub struct AudioRingBuffer {
+    pub samples_per_second: u32,
+    /// Bytes of one sample of every channel.
+    pub bytes_per_sample: u32,
+    pub buffer_size: u32,
+    /// Samples to stay ahead of the write cursor, the time a frame may take
+    /// before the card runs out of written samples.
+    pub latency_sample_count: u32,
+    running_sample_index: u64,
+}
+
+impl AudioRingBuffer {
+    pub fn new(
+        samples_per_second: u32,
+        bytes_per_sample: u32,
+        buffer_size: u32,
+        latency_sample_count: u32,
+    ) -> Self {
+        assert!(
+            bytes_per_sample > 0
+                && buffer_size.is_multiple_of(bytes_per_sample),
+            "the ring must hold a whole number of samples"
+        );
+        assert!(
+            latency_sample_count * bytes_per_sample < buffer_size / 2,
+            "the latency must fit into half the ring"
+        );
+        Self {
+            samples_per_second,
+            bytes_per_sample,
+            buffer_size,
+            latency_sample_count,
+            running_sample_index: 0,
+        }
+    }
+
+    /// Samples written or skipped since the ring started playing.
+    pub fn running_sample_index(&self) -> u64 {
+        self.running_sample_index
+    }
+
+    /// Works out the next write from the cursors the card reports.
+    ///
+    /// The write continues where the last one stopped and runs up to
+    /// `latency_sample_count` samples past the write cursor. When the play
+    /// cursor already got past the end of the last write, because a frame
+    /// took longer than the latency, the samples up to the write cursor are
+    /// skipped. When the last write still reaches past the target nothing is
+    /// written.
+    ///
+    /// Cursors only say where in the ring the card is, so a write position
+    /// more than half the ring ahead of the play cursor counts as behind it.
+    /// The latency plus the gap between the cursors has to stay below that,
+    /// and a frame taking longer than the whole ring goes unnoticed.
+    pub fn next_write(
+        &self,
+        play_cursor: u32,
+        write_cursor: u32,
+    ) -> AudioWrite {
+        let size = self.buffer_size;
+        let bytes_per_sample = self.bytes_per_sample;
+        let mut lock_offset = ((self.running_sample_index
+            * bytes_per_sample as u64)
+            % size as u64) as u32;
+        let committed = ring_distance(play_cursor, write_cursor, size);
+        let mut skipped_samples = 0;
+        let ahead_of_play = ring_distance(play_cursor, lock_offset, size);
+        if ahead_of_play < committed || ahead_of_play >= size / 2 {
+            skipped_samples = ring_distance(lock_offset, write_cursor, size)
+                / bytes_per_sample;
+            lock_offset =
+                (lock_offset + skipped_samples * bytes_per_sample) % size;
+        }
+        let target_cursor = (write_cursor
+            + self.latency_sample_count * bytes_per_sample)
+            % size;
+        let bytes_to_write = if ring_distance(play_cursor, lock_offset, size)
+            > ring_distance(play_cursor, target_cursor, size)
+        {
+            0
+        } else {
+            let bytes = ring_distance(lock_offset, target_cursor, size);
+            bytes - bytes % bytes_per_sample
+        };
+        AudioWrite {
+            lock_offset,
+            bytes_to_write,
+            sample_count: bytes_to_write / bytes_per_sample,
+            target_cursor,
+            skipped_samples,
+        }
+    }
+
+    /// The byte ranges of the ring `write` covers, the second one is where
+    /// it continues at the start of the ring and usually empty.
+    pub fn regions(&self, write: &AudioWrite) -> [Range<usize>; 2] {
+        let start = write.lock_offset as usize;
+        let end = start + write.bytes_to_write as usize;
+        let size = self.buffer_size as usize;
+        [start..end.min(size), 0..end.saturating_sub(size)]
+    }
+
+    /// Moves past `write` once its samples are in the ring.
+    pub fn commit(&mut self, write: &AudioWrite) {
+        self.running_sample_index +=
+            write.skipped_samples as u64 + write.sample_count as u64;
+    }
+}
+
+/// A sound card that plays a ring buffer of interleaved `i16` samples at
+/// `samples_per_second` of the clock it was started with.
+#[derive(Debug, Clone)]
+pub struct SimulatedAudioDevice {
+    samples_per_second: u32,
+    bytes_per_sample: u32,
+    buffer: Vec<u8>,
+    start: u64,
+    /// Bytes the write cursor runs ahead of the play cursor.
+    pub write_cursor_lead: u32,
+    /// The cursors move in steps of this many bytes, like cards that only
+    /// report a position every few milliseconds.
+    pub cursor_granularity: u32,
+}
+
+impl SimulatedAudioDevice {
+    /// Starts playing a silent ring of `buffer_size` bytes now. The write
+    /// cursor leads by 10ms and the cursors move a sample at a time.
+    pub fn new<C: Clock + ?Sized>(
+        samples_per_second: u32,
+        bytes_per_sample: u32,
+        buffer_size: u32,
+        clock: &C,
+    ) -> Self {
+        Self {
+            samples_per_second,
+            bytes_per_sample,
+            buffer: vec![0; buffer_size as usize],
+            start: clock.now(),
+            write_cursor_lead: samples_per_second / 100 * bytes_per_sample,
+            cursor_granularity: bytes_per_sample,
+        }
+    }
+
+    pub fn buffer_size(&self) -> u32 {
+        self.buffer.len() as u32
+    }
+
+    /// The ring as the card sees it.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Bytes played since the device started.
+    pub fn played_bytes<C: Clock + ?Sized>(&self, clock: &C) -> u64 {
+        let ticks = clock.now().saturating_sub(self.start) as u128;
+        let samples = ticks * self.samples_per_second as u128
+            / clock.ticks_per_second() as u128;
+        let bytes = samples as u64 * self.bytes_per_sample as u64;
+        let granularity = self.cursor_granularity.max(1) as u64;
+        bytes - bytes % granularity
+    }
+
+    /// The play and write cursors, like DirectSound's `GetCurrentPosition`.
+    pub fn current_position<C: Clock + ?Sized>(&self, clock: &C) -> (u32, u32) {
+        let size = self.buffer.len() as u64;
+        let played = self.played_bytes(clock);
+        let play_cursor = played % size;
+        let write_cursor = (played + self.write_cursor_lead as u64) % size;
+        (play_cursor as u32, write_cursor as u32)
+    }
+
+    /// Copies the interleaved `samples` into the regions of `write`.
+    pub fn write(
+        &mut self,
+        ring: &AudioRingBuffer,
+        write: &AudioWrite,
+        samples: &[i16],
+    ) {
+        let mut bytes = samples.iter().flat_map(|sample| sample.to_le_bytes());
+        for region in ring.regions(write).iter().cloned() {
+            for (byte, sample_byte) in
+                self.buffer[region].iter_mut().zip(&mut bytes)
+            {
+                *byte = sample_byte;
+            }
+        }
+    }
+}
+
+/// The cursors of one frame, see `AudioSyncMarkers`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AudioSyncMarker {
+    pub play_cursor: u32,
+    pub write_cursor: u32,
+    /// The `AudioWrite::target_cursor` worked out from the two cursors.
+    pub target_cursor: u32,
+}
+
+impl AudioSyncMarker {
+    pub fn new(
+        play_cursor: u32,
+        write_cursor: u32,
+        write: &AudioWrite,
+    ) -> Self {
+        Self {
+            play_cursor,
+            write_cursor,
+            target_cursor: write.target_cursor,
+        }
+    }
+}
+
+pub const PLAY_CURSOR_COLOR: u32 = render::rgb(0xff, 0xff, 0xff);
+pub const WRITE_CURSOR_COLOR: u32 = render::rgb(0xff, 0x00, 0x00);
+pub const TARGET_CURSOR_COLOR: u32 = render::rgb(0xff, 0xff, 0x00);
+
+const SYNC_PADDING: i32 = 16;
+const SYNC_LINE_HEIGHT: i32 = 64;
+
+/// The markers of the last `capacity` frames.
+#[derive(Debug, Clone)]
+pub struct AudioSyncMarkers {
+    capacity: usize,
+    // Ring of markers, `next` is where the next one goes.
+    markers: Vec<AudioSyncMarker>,
+    next: usize,
+}
+
+impl AudioSyncMarkers {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "there must be room for a marker");
+        Self {
+            capacity,
+            markers: Vec::with_capacity(capacity),
+            next: 0,
+        }
+    }
+
+    pub fn record(&mut self, marker: AudioSyncMarker) {
+        if self.markers.len() < self.capacity {
+            self.markers.push(marker);
+        } else {
+            self.markers[self.next] = marker;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    /// The markers kept, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &AudioSyncMarker> {
+        let (newer, older) = self.markers.split_at(self.next);
+        older.iter().chain(newer.iter())
+    }
+
+    pub fn latest(&self) -> Option<&AudioSyncMarker> {
+        self.iter().last()
+    }
+
+    /// Draws every marker as vertical lines across a band at the top of
+    /// `buffer`, which spans the `sound_buffer_size` bytes of the ring. The
+    /// latest marker is drawn again in a band below so it stands out.
+    pub fn draw(&self, buffer: &mut GameScreenBuffer, sound_buffer_size: u32) {
+        let width = buffer.width() - 2 * SYNC_PADDING;
+        if width <= 0 || sound_buffer_size == 0 {
+            return;
+        }
+        let scale = width as f32 / sound_buffer_size as f32;
+        let draw_marker = |buffer: &mut GameScreenBuffer,
+                           marker: &AudioSyncMarker,
+                           top: i32| {
+            let lines = [
+                (marker.play_cursor, PLAY_CURSOR_COLOR),
+                (marker.write_cursor, WRITE_CURSOR_COLOR),
+                (marker.target_cursor, TARGET_CURSOR_COLOR),
+            ];
+            for &(cursor, color) in lines.iter() {
+                let x = SYNC_PADDING + (cursor as f32 * scale) as i32;
+                render::fill_rect(
+                    buffer,
+                    x,
+                    top,
+                    x + 1,
+                    top + SYNC_LINE_HEIGHT,
+                    color,
+                );
+            }
+        };
+        for marker in self.iter() {
+            draw_marker(buffer, marker, SYNC_PADDING);
+        }
+        if let Some(latest) = self.latest() {
+            draw_marker(buffer, latest, 2 * SYNC_PADDING + SYNC_LINE_HEIGHT);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Loads the game from the crate's `cdylib` and reloads it when it is
+//! rebuilt.
+
+use handmade_hero::game_code::{
+    GameGetSoundSamples, GameUpdateAndRender, GET_SOUND_SAMPLES_SYMBOL,
+    UPDATE_AND_RENDER_SYMBOL,
+};
+use handmade_hero::GameCode;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A game library opened with `dlopen`.
+///
+/// The library is loaded from a copy so the build can overwrite the
+/// original while the game runs, and so `dlopen` sees a new file on every
+/// reload instead of handing back the library it already has open.
+pub struct GameLibrary {
+    path: PathBuf,
+    last_write_time: SystemTime,
+    loaded: LoadedLibrary,
+    reloads: u32,
+}
+
+struct LoadedLibrary {
+    handle: *mut libc::c_void,
+    copy: PathBuf,
+    code: GameCode,
+}
+
+impl Drop for LoadedLibrary {
+    fn drop(&mut self) {
+        // SAFETY: nothing calls into the library once it is dropped.
+        unsafe { libc::dlclose(self.handle) };
+        let _ = std::fs::remove_file(&self.copy);
+    }
+}
+
+fn error(message: String) -> std::io::Error {
+    std::io::Error::other(message)
+}
+
+fn dl_error() -> String {
+    // SAFETY: dlerror returns null or a NUL terminated string that stays
+    // valid until the next dl call on this thread.
+    unsafe {
+        let message = libc::dlerror();
+        if message.is_null() {
+            return "unknown error".into();
+        }
+        std::ffi::CStr::from_ptr(message)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl LoadedLibrary {
+    fn open(path: &Path, copy: PathBuf) -> std::io::Result<Self> {
+        use std::os::unix::ffi::OsStrExt;
+
+        std::fs::copy(path, &copy)?;
+        let name = std::ffi::CString::new(copy.as_os_str().as_bytes())
+            .map_err(|_| error(format!("{} contains NUL", copy.display())))?;
+        // SAFETY: loading the library runs its initialisers, which a Rust
+        // cdylib has none of beyond std's.
+        let handle = unsafe {
+            libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL)
+        };
+        if handle.is_null() {
+            let message = dl_error();
+            let _ = std::fs::remove_file(&copy);
+            return Err(error(format!("{}: {}", path.display(), message)));
+        }
+        // From here on dropping `library` closes the handle again.
+        let mut library = Self {
+            handle,
+            copy,
+            code: GameCode::default(),
+        };
+        let update_and_render = library.symbol(UPDATE_AND_RENDER_SYMBOL)?;
+        let get_sound_samples = library.symbol(GET_SOUND_SAMPLES_SYMBOL)?;
+        // SAFETY: the symbols are the crate's exports, which have exactly
+        // these signatures.
+        library.code = unsafe {
+            GameCode {
+                update_and_render: std::mem::transmute::<
+                    *mut libc::c_void,
+                    GameUpdateAndRender,
+                >(update_and_render),
+                get_sound_samples: std::mem::transmute::<
+                    *mut libc::c_void,
+                    GameGetSoundSamples,
+                >(get_sound_samples),
+            }
+        };
+        Ok(library)
+    }
+
+    fn symbol(&self, name: &[u8]) -> std::io::Result<*mut libc::c_void> {
+        // SAFETY: `name` is one of the NUL terminated symbol constants.
+        let symbol = unsafe { libc::dlsym(self.handle, name.as_ptr().cast()) };
+        if symbol.is_null() {
+            return Err(error(format!(
+                "{}: {}",
+                String::from_utf8_lossy(&name[..name.len() - 1]),
+                dl_error()
+            )));
+        }
+        Ok(symbol)
+    }
+}
+
+fn modified(path: &Path) -> std::io::Result<SystemTime> {
+    std::fs::metadata(path)?.modified()
+}
+
+impl GameLibrary {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let last_write_time = modified(path)?;
+        let loaded = LoadedLibrary::open(path, Self::copy_path(path, 0))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            last_write_time,
+            loaded,
+            reloads: 0,
+        })
+    }
+
+    fn copy_path(path: &Path, reloads: u32) -> PathBuf {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        std::env::temp_dir().join(format!(
+            "{}.{}.{}.loaded",
+            name,
+            std::process::id(),
+            reloads
+        ))
+    }
+
+    pub fn code(&self) -> GameCode {
+        self.loaded.code
+    }
+
+    pub fn reloads(&self) -> u32 {
+        self.reloads
+    }
+
+    /// Swaps in the library on disk if it changed since it was loaded.
+    ///
+    /// A library that fails to load, for example because the linker is
+    /// still writing it, is reported and retried on the next call while
+    /// the old code keeps running.
+    pub fn reload_if_changed(&mut self) -> std::io::Result<bool> {
+        let write_time = modified(&self.path)?;
+        if write_time == self.last_write_time {
+            return Ok(false);
+        }
+        let copy = Self::copy_path(&self.path, self.reloads + 1);
+        self.loaded = LoadedLibrary::open(&self.path, copy)?;
+        self.last_write_time = write_time;
+        self.reloads += 1;
+        Ok(true)
+    }
+}
@@ -0,0 +1,405 @@
+#[cfg(unix)]
+use crate::game_library::GameLibrary;
+use crate::Config;
+use handmade_hero::audio_ring::{
+    AudioRingBuffer, AudioSyncMarker, AudioSyncMarkers, AudioWrite,
+    SimulatedAudioDevice,
+};
+use handmade_hero::golden;
+use handmade_hero::midi::{MidiFile, MidiPlayer, ProgramMap};
+use handmade_hero::replay::{self, InputLoop};
+use handmade_hero::timing::{Clock, FramePacer};
+use handmade_hero::wav::WavWriter;
+use handmade_hero::{GameCode, GameInput, GameMemory, GameScreenBuffer, Music};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+#[cfg(unix)]
+type HeadlessClock = handmade_hero::timing::MonotonicClock;
+#[cfg(not(unix))]
+type HeadlessClock = handmade_hero::timing::SystemClock;
+
+pub struct HeadlessPlatform<'a> {
+    config: &'a Config,
+    screen_memory: Vec<u32>,
+    frame: u32,
+    frame_limit: u32,
+    audio_recording: Option<WavWriter<BufWriter<File>>>,
+    audio_error: Option<std::io::Error>,
+    game_memory: GameMemory,
+    input_loop: InputLoop,
+    clock: HeadlessClock,
+    frame_pacer: Option<FramePacer>,
+    sound_output: Option<SimulatedSoundOutput>,
+    #[cfg(unix)]
+    game_library: Option<GameLibrary>,
+    // Unmaps game memory placed at a fixed address, declared after
+    // `game_memory` so it goes last.
+    _mapping: Option<FixedMapping>,
+}
+
+impl<'a> HeadlessPlatform<'a> {
+    pub fn new(config: &'a Config) -> std::io::Result<Self> {
+        let audio_recording = match &config.record_audio {
+            Some(path) => Some(WavWriter::new(
+                BufWriter::new(File::create(path)?),
+                config.samples_per_second,
+                2,
+            )?),
+            None => None,
+        };
+        let (mut game_memory, mapping) = match config.base_address {
+            Some(address) => {
+                let mapping = FixedMapping::new(
+                    address,
+                    config.permanent_storage_size
+                        + config.transient_storage_size,
+                )?;
+                // SAFETY: the mapping covers both storages, is page aligned
+                // and lives as long as the platform.
+                let memory = unsafe {
+                    GameMemory::new(
+                        mapping.base,
+                        config.permanent_storage_size,
+                        config.transient_storage_size,
+                    )
+                };
+                (memory, Some(mapping))
+            }
+            None => (
+                GameMemory::allocate(
+                    config.permanent_storage_size,
+                    config.transient_storage_size,
+                ),
+                None,
+            ),
+        };
+        if let Some(path) = &config.midi {
+            let file = MidiFile::from_smf(&std::fs::read(path)?)
+                .map_err(|error| error.to_string())
+                .and_then(|file| {
+                    file.store(&mut game_memory)
+                        .map_err(|error| error.to_string())
+                })
+                .map_err(|error| {
+                    std::io::Error::other(format!(
+                        "{}: {}",
+                        path.display(),
+                        error
+                    ))
+                })?;
+            game_memory.state().music = Some(Music::Midi(MidiPlayer::new(
+                file,
+                ProgramMap::general_midi(),
+            )));
+        }
+        #[cfg(unix)]
+        let game_library = match &config.game_library {
+            Some(path) => Some(GameLibrary::load(path)?),
+            None => None,
+        };
+        let mut input_loop = InputLoop::default();
+        let mut frame_limit = config.frames;
+        if let Some(path) = &config.replay {
+            let file = BufReader::new(File::open(path)?);
+            input_loop =
+                InputLoop::read_from(file, &game_memory).map_err(|error| {
+                    std::io::Error::other(format!(
+                        "{}: {}",
+                        path.display(),
+                        error
+                    ))
+                })?;
+            frame_limit = input_loop.recorded_frames() as u32;
+            input_loop.start_playback(&mut game_memory);
+        }
+        if config.record_replay.is_some() {
+            input_loop.start_recording(&game_memory);
+        }
+        let clock = HeadlessClock::default();
+        let frame_pacer = if config.pace {
+            Some(FramePacer::new(config.frames_per_second as f32, &clock))
+        } else {
+            None
+        };
+        let sound_output = if config.audio_sync {
+            Some(SimulatedSoundOutput::new(config, &clock))
+        } else {
+            None
+        };
+        Ok(Self {
+            config,
+            screen_memory: vec![0u32; (config.width * config.height) as usize],
+            frame: 0,
+            frame_limit,
+            audio_recording,
+            audio_error: None,
+            game_memory,
+            input_loop,
+            clock,
+            frame_pacer,
+            sound_output,
+            #[cfg(unix)]
+            game_library,
+            _mapping: mapping,
+        })
+    }
+
+    pub fn frames_run(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        replay::state_hash(&self.game_memory)
+    }
+
+    /// Completes the audio recording, reporting the first write that failed,
+    /// and writes the replay file and the last frame.
+    pub fn finish(self) -> std::io::Result<()> {
+        if let Some(error) = self.audio_error {
+            return Err(error);
+        }
+        if let Some(recording) = self.audio_recording {
+            recording.finish()?;
+        }
+        if let Some(path) = &self.config.record_replay {
+            self.input_loop
+                .write_to(BufWriter::new(File::create(path)?))?;
+        }
+        if let Some(path) = &self.config.save_frame {
+            let frame = GameScreenBuffer::from_vec(
+                self.screen_memory,
+                self.config.width,
+                self.config.height,
+            );
+            std::fs::write(path, golden::encode_ppm(&frame))?;
+        }
+        Ok(())
+    }
+}
+
+/// A simulated sound card the platform writes to like the Win32 layer writes
+/// to DirectSound, recording its cursors every frame.
+struct SimulatedSoundOutput {
+    ring: AudioRingBuffer,
+    device: SimulatedAudioDevice,
+    markers: AudioSyncMarkers,
+    // The write worked out for this frame.
+    write: Option<AudioWrite>,
+}
+
+impl SimulatedSoundOutput {
+    fn new(config: &Config, clock: &HeadlessClock) -> Self {
+        // One second of sound and 50ms of latency, like the Win32 layer.
+        let buffer_size = config.samples_per_second * config.bytes_per_sample;
+        let ring = AudioRingBuffer::new(
+            config.samples_per_second,
+            config.bytes_per_sample,
+            buffer_size,
+            config.samples_per_second / 20,
+        );
+        let device = SimulatedAudioDevice::new(
+            config.samples_per_second,
+            config.bytes_per_sample,
+            buffer_size,
+            clock,
+        );
+        Self {
+            ring,
+            device,
+            // Half a second of frames.
+            markers: AudioSyncMarkers::new(
+                (config.frames_per_second as usize / 2).max(1),
+            ),
+            write: None,
+        }
+    }
+}
+
+/// Zeroed pages mapped at a caller chosen address.
+#[cfg_attr(not(unix), allow(dead_code))]
+struct FixedMapping {
+    base: *mut u8,
+    size: usize,
+}
+
+impl FixedMapping {
+    #[cfg(unix)]
+    fn new(address: usize, size: usize) -> std::io::Result<Self> {
+        let size = size.max(1);
+        // SAFETY: MAP_FIXED_NOREPLACE fails instead of replacing anything
+        // already mapped at `address`.
+        let base = unsafe {
+            libc::mmap(
+                address as *mut libc::c_void,
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE
+                    | libc::MAP_ANONYMOUS
+                    | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        if base as usize != address {
+            // Kernels before 4.17 treat the flag as a hint.
+            unsafe { libc::munmap(base, size) };
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("could not map game memory at {:#x}", address),
+            ));
+        }
+        Ok(Self {
+            base: base.cast(),
+            size,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn new(_address: usize, _size: usize) -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "a fixed base address is only supported on unix",
+        ))
+    }
+}
+
+impl Drop for FixedMapping {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: `new` mapped exactly this range.
+        unsafe {
+            libc::munmap(self.base.cast(), self.size);
+        }
+    }
+}
+
+impl<'a> handmade_hero::Platform for HeadlessPlatform<'a> {
+    fn poll_input(&mut self, input: &mut GameInput) -> bool {
+        if self.frame == self.frame_limit {
+            return false;
+        }
+        // Scripted keyboard: keep walking right, go up and down every
+        // half second.
+        let half_seconds =
+            self.frame / (self.config.frames_per_second / 2).max(1);
+        let keyboard = input.keyboard_mut();
+        keyboard.is_connected = true;
+        keyboard.move_right.process(true);
+        keyboard.move_up.process(half_seconds.is_multiple_of(2));
+        keyboard.move_down.process(!half_seconds.is_multiple_of(2));
+
+        if let Some((start, length)) = self.config.input_loop {
+            if self.frame == start {
+                self.input_loop.start_recording(&self.game_memory);
+            }
+            if self.frame == start + length {
+                self.input_loop.start_playback(&mut self.game_memory);
+            }
+        }
+        self.input_loop.process(input, &mut self.game_memory);
+        self.frame += 1;
+        true
+    }
+
+    #[cfg(unix)]
+    fn game_code(&mut self) -> GameCode {
+        let library = match self.game_library.as_mut() {
+            Some(library) => library,
+            None => return GameCode::default(),
+        };
+        match library.reload_if_changed() {
+            Ok(true) => println!("reloaded game code ({})", library.reloads()),
+            Ok(false) => {}
+            Err(error) => eprintln!("could not reload game code: {}", error),
+        }
+        library.code()
+    }
+
+    fn game_memory(&mut self) -> &mut GameMemory {
+        &mut self.game_memory
+    }
+
+    fn back_buffer(&mut self) -> GameScreenBuffer {
+        let bytes_per_pixel = core::mem::size_of::<u32>();
+        // SAFETY: `screen_memory` holds `width * height` pixels and is only
+        // touched through the buffer handed out for the current frame.
+        unsafe {
+            GameScreenBuffer::new(
+                self.screen_memory.as_mut_ptr().cast(),
+                bytes_per_pixel,
+                self.config.height,
+                self.config.width,
+                self.config.width as usize * bytes_per_pixel,
+            )
+        }
+    }
+
+    fn samples_per_second(&self) -> u32 {
+        self.config.samples_per_second
+    }
+
+    fn sound_samples_needed(&mut self) -> u32 {
+        let output = match self.sound_output.as_mut() {
+            Some(output) => output,
+            // Fixed timestep: every frame asks for exactly one frame's worth
+            // of samples.
+            None => {
+                return self.config.samples_per_second
+                    / self.config.frames_per_second
+            }
+        };
+        let (play_cursor, write_cursor) =
+            output.device.current_position(&self.clock);
+        let write = output.ring.next_write(play_cursor, write_cursor);
+        output.markers.record(AudioSyncMarker::new(
+            play_cursor,
+            write_cursor,
+            &write,
+        ));
+        output.write = Some(write);
+        write.sample_count
+    }
+
+    fn submit_audio(&mut self, samples: &[i16]) {
+        if let Some(output) = self.sound_output.as_mut() {
+            if let Some(write) = output.write.take() {
+                output.device.write(&output.ring, &write, samples);
+                output.ring.commit(&write);
+            }
+        }
+        let recording = match self.audio_recording.as_mut() {
+            Some(recording) if self.audio_error.is_none() => recording,
+            _ => return,
+        };
+        if let Err(error) = recording.write_samples(samples) {
+            self.audio_error = Some(error);
+        }
+    }
+
+    fn wait_for_frame_end(&mut self) -> bool {
+        let clock = &mut self.clock;
+        self.frame_pacer
+            .as_mut()
+            .is_some_and(|pacer| pacer.wait_for_frame_end(clock).missed)
+    }
+
+    fn present_frame(&mut self) {
+        if self.sound_output.is_none() {
+            return;
+        }
+        let mut video_buffer = self.back_buffer();
+        if let Some(output) = self.sound_output.as_ref() {
+            output
+                .markers
+                .draw(&mut video_buffer, output.device.buffer_size());
+        }
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &self.clock
+    }
+}
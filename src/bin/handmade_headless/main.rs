@@ -0,0 +1,257 @@
+//! Headless platform layer.
+//!
+//! Runs `handmade_hero::run_game_loop` on plain heap memory with a
+//! fixed timestep so the game layer can be exercised without a window or a
+//! sound card.
+
+#[cfg(unix)]
+mod game_library;
+mod headless_platform;
+
+use headless_platform::HeadlessPlatform;
+
+const USAGE: &str = "usage: handmade_headless [--frames N] [--fps N] \
+                     [--width N] [--height N] [--record-audio out.wav] \
+                     [--permanent-mb N] [--transient-mb N] \
+                     [--base-address ADDR] [--game-library PATH] \
+                     [--loop START:LENGTH] [--record-replay FILE] \
+                     [--replay FILE [--expect-hash HASH]] [--pace] \
+                     [--frame-stats-csv FILE] [--audio-sync] \
+                     [--save-frame out.ppm] [--midi FILE]";
+
+pub struct Config {
+    pub frames: u32,
+    pub frames_per_second: u32,
+    pub width: i32,
+    pub height: i32,
+    pub samples_per_second: u32,
+    pub bytes_per_sample: u32,
+    /// Where to write every sample the game produced.
+    pub record_audio: Option<std::path::PathBuf>,
+    pub permanent_storage_size: usize,
+    pub transient_storage_size: usize,
+    /// Address to map game memory at so pointers into it stay the same
+    /// between runs.
+    pub base_address: Option<usize>,
+    /// The game's shared library, reloaded whenever it is rebuilt. The game
+    /// linked into the runner is used without one.
+    pub game_library: Option<std::path::PathBuf>,
+    /// Records the input of `LENGTH` frames from frame `START` on, then
+    /// plays it back in a loop for the rest of the run.
+    pub input_loop: Option<(u32, u32)>,
+    /// Records the whole run as a replay file.
+    pub record_replay: Option<std::path::PathBuf>,
+    /// Plays back a replay file instead of the scripted input, running as
+    /// many frames as it holds.
+    pub replay: Option<std::path::PathBuf>,
+    /// State hash the run has to end with.
+    pub expect_hash: Option<u64>,
+    /// Holds frames to `frames_per_second` of wall time instead of running
+    /// them as fast as possible.
+    pub pace: bool,
+    /// Where to write the time of every frame when the run ends.
+    pub frame_stats_csv: Option<std::path::PathBuf>,
+    /// Plays audio through a simulated sound card running in real time and
+    /// draws its cursors over every frame.
+    pub audio_sync: bool,
+    /// Where to write the last frame as a PPM image.
+    pub save_frame: Option<std::path::PathBuf>,
+    /// A Standard MIDI File the game plays as its music, on General MIDI
+    /// stand in instruments.
+    pub midi: Option<std::path::PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            frames: 60,
+            frames_per_second: 30,
+            width: 1280,
+            height: 720,
+            samples_per_second: 48000,
+            bytes_per_sample: (core::mem::size_of::<u16>() * 2) as u32,
+            record_audio: None,
+            permanent_storage_size: 64 * 1024 * 1024,
+            transient_storage_size: 256 * 1024 * 1024,
+            base_address: None,
+            game_library: None,
+            input_loop: None,
+            record_replay: None,
+            replay: None,
+            expect_hash: None,
+            pace: false,
+            frame_stats_csv: None,
+            audio_sync: false,
+            save_frame: None,
+            midi: None,
+        }
+    }
+}
+
+impl Config {
+    fn from_args<I: Iterator<Item = String>>(
+        mut args: I,
+    ) -> Result<Self, String> {
+        let mut config = Config::default();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("{} expects a value", name))
+            };
+            match arg.as_str() {
+                "--frames" => config.frames = parse(&arg, &value(&arg)?)?,
+                "--fps" => {
+                    config.frames_per_second = parse(&arg, &value(&arg)?)?
+                }
+                "--width" => config.width = parse(&arg, &value(&arg)?)?,
+                "--height" => config.height = parse(&arg, &value(&arg)?)?,
+                "--record-audio" => {
+                    config.record_audio = Some(value(&arg)?.into())
+                }
+                "--permanent-mb" => {
+                    config.permanent_storage_size =
+                        parse::<usize>(&arg, &value(&arg)?)? * 1024 * 1024
+                }
+                "--transient-mb" => {
+                    config.transient_storage_size =
+                        parse::<usize>(&arg, &value(&arg)?)? * 1024 * 1024
+                }
+                "--base-address" => {
+                    let address = value(&arg)?;
+                    let digits = address.trim_start_matches("0x");
+                    config.base_address = Some(
+                        usize::from_str_radix(digits, 16).map_err(|_| {
+                            format!("invalid value for {}: {}", arg, address)
+                        })?,
+                    )
+                }
+                "--game-library" if cfg!(unix) => {
+                    config.game_library = Some(value(&arg)?.into())
+                }
+                "--loop" => {
+                    let range = value(&arg)?;
+                    let (start, length) =
+                        range.split_once(':').ok_or_else(|| {
+                            format!("{} expects START:LENGTH", arg)
+                        })?;
+                    config.input_loop =
+                        Some((parse(&arg, start)?, parse(&arg, length)?))
+                }
+                "--record-replay" => {
+                    config.record_replay = Some(value(&arg)?.into())
+                }
+                "--pace" => config.pace = true,
+                "--audio-sync" => config.audio_sync = true,
+                "--save-frame" => config.save_frame = Some(value(&arg)?.into()),
+                "--midi" => config.midi = Some(value(&arg)?.into()),
+                "--frame-stats-csv" => {
+                    config.frame_stats_csv = Some(value(&arg)?.into())
+                }
+                "--replay" => config.replay = Some(value(&arg)?.into()),
+                "--expect-hash" => {
+                    let hash = value(&arg)?;
+                    config.expect_hash = Some(parse_hex(&arg, &hash)?)
+                }
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        if config.frames_per_second == 0 {
+            return Err("--fps must be greater than 0".into());
+        }
+        if config.width <= 0 || config.height <= 0 {
+            return Err("--width and --height must be greater than 0".into());
+        }
+        if config.base_address.is_some_and(|address| {
+            !address.is_multiple_of(handmade_hero::GameMemory::ALIGNMENT)
+        }) {
+            return Err(format!(
+                "--base-address must be a multiple of {:#x}",
+                handmade_hero::GameMemory::ALIGNMENT
+            ));
+        }
+        let input_sources = [
+            config.input_loop.is_some(),
+            config.record_replay.is_some(),
+            config.replay.is_some(),
+        ];
+        if input_sources.iter().filter(|&&used| used).count() > 1 {
+            return Err(
+                "--loop, --record-replay and --replay exclude each other"
+                    .into(),
+            );
+        }
+        if config.expect_hash.is_some() && config.replay.is_none() {
+            return Err("--expect-hash needs --replay".into());
+        }
+        Ok(config)
+    }
+}
+
+fn parse<T: core::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", name, value))
+}
+
+fn parse_hex(name: &str, value: &str) -> Result<u64, String> {
+    u64::from_str_radix(value.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("invalid value for {}: {}", name, value))
+}
+
+fn main() {
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let start = std::time::Instant::now();
+    let mut platform = match HeadlessPlatform::new(&config) {
+        Ok(platform) => platform,
+        Err(error) => {
+            eprintln!("could not start: {}", error);
+            std::process::exit(1);
+        }
+    };
+    // Summarizes every simulated second.
+    let mut frame_stats =
+        handmade_hero::FrameStats::new(config.frames_per_second as usize);
+    if config.frame_stats_csv.is_some() {
+        frame_stats = frame_stats.with_history();
+    }
+    handmade_hero::run_game_loop(&mut platform, &mut frame_stats);
+    let elapsed = start.elapsed().as_secs_f32();
+    println!(
+        "frames:{},\t simulated s:{:.2},\t wall s:{:.2},\t missed:{}",
+        platform.frames_run(),
+        platform.frames_run() as f32 / config.frames_per_second as f32,
+        elapsed,
+        frame_stats.missed_vsyncs()
+    );
+    if let Some(path) = &config.frame_stats_csv {
+        let written = std::fs::File::create(path).and_then(|file| {
+            frame_stats.write_csv(std::io::BufWriter::new(file))
+        });
+        if let Err(error) = written {
+            eprintln!("could not write {}: {}", path.display(), error);
+            std::process::exit(1);
+        }
+    }
+    let state_hash = platform.state_hash();
+    println!("state hash:{:016x}", state_hash);
+    if let Err(error) = platform.finish() {
+        eprintln!("could not finish the recordings: {}", error);
+        std::process::exit(1);
+    }
+    if let Some(expected) = config.expect_hash {
+        if expected != state_hash {
+            eprintln!(
+                "replay ended with state hash {:016x}, expected {:016x}",
+                state_hash, expected
+            );
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Uncompressed BMP loading and alpha blended blitting.
+
+use crate::bytes::{read_u16_le, read_u32_le, Truncated};
+use crate::GameScreenBuffer;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+/// A decoded image with rows stored top-down as `0xAARRGGBB`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedBitmap {
+    pub width: i32,
+    pub height: i32,
+    /// Pixel of the bitmap that lands on the position passed to
+    /// `draw_bitmap`, measured from the top left corner.
+    pub align_x: i32,
+    pub align_y: i32,
+    pub pixels: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BitmapError {
+    NotABitmap,
+    Truncated,
+    UnsupportedHeader(u32),
+    UnsupportedBitCount(u16),
+    UnsupportedCompression(u32),
+    InvalidDimensions(i32, i32),
+}
+
+impl std::fmt::Display for BitmapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitmapError::NotABitmap => write!(f, "missing BM signature"),
+            BitmapError::Truncated => write!(f, "file ends before its pixels"),
+            BitmapError::UnsupportedHeader(size) => {
+                write!(f, "unsupported info header of {} bytes", size)
+            }
+            BitmapError::UnsupportedBitCount(bits) => {
+                write!(f, "unsupported {} bits per pixel", bits)
+            }
+            BitmapError::UnsupportedCompression(compression) => {
+                write!(f, "unsupported compression {}", compression)
+            }
+            BitmapError::InvalidDimensions(width, height) => {
+                write!(f, "invalid dimensions {}x{}", width, height)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BitmapError {}
+
+impl From<Truncated> for BitmapError {
+    fn from(_: Truncated) -> Self {
+        BitmapError::Truncated
+    }
+}
+
+// Moves the bits selected by `mask` down to a byte.
+fn extract_channel(value: u32, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let bits = (mask >> shift).count_ones();
+    let channel = (value & mask) >> shift;
+    if bits >= 8 {
+        channel >> (bits - 8)
+    } else {
+        channel * 255 / ((1 << bits) - 1)
+    }
+}
+
+impl LoadedBitmap {
+    /// Decodes a 24 or 32 bit uncompressed BMP file.
+    ///
+    /// Both bottom-up (positive `biHeight`) and top-down (negative
+    /// `biHeight`) files end up top-down in `pixels`. 32 bit files without
+    /// an alpha mask, or whose alpha is zero everywhere, are treated as
+    /// opaque.
+    pub fn from_bmp(data: &[u8]) -> Result<Self, BitmapError> {
+        if data.get(0..2) != Some(b"BM") {
+            return Err(BitmapError::NotABitmap);
+        }
+        let pixel_offset = read_u32_le(data, 10)? as usize;
+        let header_size = read_u32_le(data, 14)?;
+        if header_size < 40 {
+            return Err(BitmapError::UnsupportedHeader(header_size));
+        }
+        let width = read_u32_le(data, 18)? as i32;
+        let height = read_u32_le(data, 22)? as i32;
+        let bit_count = read_u16_le(data, 28)?;
+        let compression = read_u32_le(data, 30)?;
+        if width <= 0 || height == 0 || height == i32::MIN {
+            return Err(BitmapError::InvalidDimensions(width, height));
+        }
+        if bit_count != 24 && bit_count != 32 {
+            return Err(BitmapError::UnsupportedBitCount(bit_count));
+        }
+
+        let (red_mask, green_mask, blue_mask, alpha_mask) = match compression {
+            BI_RGB if bit_count == 32 => {
+                (0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0xff00_0000)
+            }
+            BI_RGB => (0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0),
+            BI_BITFIELDS if bit_count == 32 => {
+                // The masks follow a plain BITMAPINFOHEADER, later headers
+                // carry them (and the alpha mask) inside.
+                let masks = 14 + 40;
+                let alpha_mask = if header_size >= 56 {
+                    read_u32_le(data, masks + 12)?
+                } else {
+                    0
+                };
+                (
+                    read_u32_le(data, masks)?,
+                    read_u32_le(data, masks + 4)?,
+                    read_u32_le(data, masks + 8)?,
+                    alpha_mask,
+                )
+            }
+            _ => return Err(BitmapError::UnsupportedCompression(compression)),
+        };
+
+        let top_down = height < 0;
+        let height = height.abs();
+        let bytes_per_pixel = bit_count as usize / 8;
+        // Rows are padded to a multiple of four bytes. Sizes no file could
+        // hold are invalid rather than left to wrap around.
+        let invalid = || BitmapError::InvalidDimensions(width, height);
+        let row_size = (width as usize)
+            .checked_mul(bytes_per_pixel)
+            .and_then(|size| size.checked_add(3))
+            .ok_or_else(invalid)?
+            & !3;
+        let end = row_size
+            .checked_mul(height as usize)
+            .and_then(|size| size.checked_add(pixel_offset))
+            .ok_or_else(invalid)?;
+        let rows = data.get(pixel_offset..end).ok_or(BitmapError::Truncated)?;
+
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height as usize {
+            let source_y = if top_down { y } else { height as usize - 1 - y };
+            let row = &rows[source_y * row_size..][..row_size];
+            for source in row.chunks_exact(bytes_per_pixel).take(width as usize)
+            {
+                let value = match source {
+                    [b, g, r] => u32::from_le_bytes([*b, *g, *r, 0]),
+                    [b, g, r, a] => u32::from_le_bytes([*b, *g, *r, *a]),
+                    _ => unreachable!(),
+                };
+                pixels.push(
+                    extract_channel(value, alpha_mask) << 24
+                        | extract_channel(value, red_mask) << 16
+                        | extract_channel(value, green_mask) << 8
+                        | extract_channel(value, blue_mask),
+                );
+            }
+        }
+        if alpha_mask == 0 || pixels.iter().all(|pixel| pixel >> 24 == 0) {
+            for pixel in pixels.iter_mut() {
+                *pixel |= 0xff00_0000;
+            }
+        }
+
+        Ok(Self {
+            width,
+            height,
+            align_x: 0,
+            align_y: 0,
+            pixels,
+        })
+    }
+
+    /// Sets the pivot `draw_bitmap` positions the bitmap by.
+    pub fn with_alignment(mut self, align_x: i32, align_y: i32) -> Self {
+        self.align_x = align_x;
+        self.align_y = align_y;
+        self
+    }
+
+    pub fn row(&self, y: i32) -> &[u32] {
+        let width = self.width as usize;
+        &self.pixels[y as usize * width..][..width]
+    }
+}
+
+/// Blends `source` (`0xAARRGGBB`, straight alpha) over `dest`.
+pub fn blend(dest: u32, source: u32) -> u32 {
+    let alpha = source >> 24;
+    match alpha {
+        0 => dest,
+        255 => source & 0x00ff_ffff,
+        _ => {
+            let mix = |shift: u32| {
+                let s = (source >> shift) & 0xff;
+                let d = (dest >> shift) & 0xff;
+                ((s * alpha + d * (255 - alpha) + 127) / 255) << shift
+            };
+            mix(16) | mix(8) | mix(0)
+        }
+    }
+}
+
+/// Draws `bitmap` with its pivot at (`x`, `y`), alpha blending every pixel
+/// over what is already in the buffer and clipping against the buffer
+/// edges.
+pub fn draw_bitmap(
+    buffer: &mut GameScreenBuffer,
+    bitmap: &LoadedBitmap,
+    x: i32,
+    y: i32,
+) {
+    let min_x = x - bitmap.align_x;
+    let min_y = y - bitmap.align_y;
+    let max_x = (min_x + bitmap.width).min(buffer.width());
+    let max_y = (min_y + bitmap.height).min(buffer.height());
+    let clip_x = (-min_x).max(0);
+    let clip_y = (-min_y).max(0);
+    let start_x = min_x + clip_x;
+    if start_x >= max_x {
+        return;
+    }
+
+    for dest_y in (min_y + clip_y)..max_y {
+        let source = &bitmap.row(dest_y - min_y)[clip_x as usize..];
+        let dest =
+            &mut buffer.row_mut(dest_y)[start_x as usize..max_x as usize];
+        for (dest, &source) in dest.iter_mut().zip(source) {
+            *dest = blend(*dest, source);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Little and big endian integers read out of file data.
+
+/// The data ends before the value being read. Loaders turn it into their
+/// own truncation error through `From`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Truncated;
+
+fn read_bytes<const N: usize>(
+    data: &[u8],
+    offset: usize,
+) -> Result<[u8; N], Truncated> {
+    let bytes = offset
+        .checked_add(N)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(Truncated)?;
+    let mut array = [0; N];
+    array.copy_from_slice(bytes);
+    Ok(array)
+}
+
+pub(crate) fn read_u16_le(
+    data: &[u8],
+    offset: usize,
+) -> Result<u16, Truncated> {
+    read_bytes(data, offset).map(u16::from_le_bytes)
+}
+
+pub(crate) fn read_u32_le(
+    data: &[u8],
+    offset: usize,
+) -> Result<u32, Truncated> {
+    read_bytes(data, offset).map(u32::from_le_bytes)
+}
+
+pub(crate) fn read_u16_be(
+    data: &[u8],
+    offset: usize,
+) -> Result<u16, Truncated> {
+    read_bytes(data, offset).map(u16::from_be_bytes)
+}
+
+pub(crate) fn read_u32_be(
+    data: &[u8],
+    offset: usize,
+) -> Result<u32, Truncated> {
+    read_bytes(data, offset).map(u32::from_be_bytes)
+}
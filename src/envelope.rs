@@ -0,0 +1,168 @@
+//! Attack/decay/sustain/release envelopes.
+//!
+//! An envelope is a gain that rises when a note starts and falls when it
+//! ends. Every stage moves linearly from wherever the level is when it
+//! begins, so a note that is cut short or started again halfway through a
+//! release continues from the current level instead of jumping, which is
+//! what clicks.
+
+use crate::replay::Encode;
+
+/// Shape of an envelope, times are in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adsr {
+    /// Time to rise from silence to the note's velocity.
+    pub attack: f32,
+    /// Time to fall from the velocity to the sustain level.
+    pub decay: f32,
+    /// Level held while the note is down, relative to the velocity.
+    pub sustain: f32,
+    /// Time to fall from the sustain level to silence once the note is
+    /// released.
+    pub release: f32,
+}
+
+impl Encode for Adsr {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.attack.encode(out);
+        self.decay.encode(out);
+        self.sustain.encode(out);
+        self.release.encode(out);
+    }
+}
+
+impl Adsr {
+    pub fn new(attack: f32, decay: f32, sustain: f32, release: f32) -> Self {
+        Self {
+            attack,
+            decay,
+            sustain,
+            release,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// No note has been played yet.
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    /// The last note has been released and faded out.
+    Done,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Envelope {
+    pub adsr: Adsr,
+    stage: Stage,
+    level: f32,
+    velocity: f32,
+    // Level change per second of an attack or release, fixed when it starts
+    // so a release takes `release` seconds from any level.
+    rate: f32,
+}
+
+impl Encode for Envelope {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.adsr.encode(out);
+        (self.stage as u8).encode(out);
+        self.level.encode(out);
+        self.velocity.encode(out);
+        self.rate.encode(out);
+    }
+}
+
+impl Envelope {
+    pub fn new(adsr: Adsr) -> Self {
+        Self {
+            adsr,
+            stage: Stage::Idle,
+            level: 0.0,
+            velocity: 0.0,
+            rate: 0.0,
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    /// Starts a note peaking at `velocity`, from the current level if the
+    /// last one is still sounding.
+    pub fn note_on(&mut self, velocity: f32) {
+        self.velocity = velocity.max(0.0);
+        self.stage = Stage::Attack;
+        // The same slope as from silence, or back down in `attack` seconds
+        // when the last note is louder.
+        let distance = (self.velocity - self.level).abs().max(self.velocity);
+        self.rate = rate(distance, self.adsr.attack);
+    }
+
+    /// Releases the note, does nothing if none is playing.
+    pub fn note_off(&mut self) {
+        match self.stage {
+            Stage::Attack | Stage::Decay | Stage::Sustain => {
+                self.stage = Stage::Release;
+                self.rate = rate(self.level, self.adsr.release);
+            }
+            Stage::Idle | Stage::Release | Stage::Done => {}
+        }
+    }
+
+    /// Advances the envelope by one sample at `samples_per_second` and
+    /// returns the level for that sample.
+    pub fn next_level(&mut self, samples_per_second: f32) -> f32 {
+        let seconds = 1.0 / samples_per_second;
+        let sustain = self.adsr.sustain.clamp(0.0, 1.0) * self.velocity;
+        match self.stage {
+            Stage::Idle | Stage::Done | Stage::Sustain => {}
+            Stage::Attack => {
+                if self.approach(self.velocity, self.rate * seconds) {
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                let step =
+                    rate(self.velocity - sustain, self.adsr.decay) * seconds;
+                if self.approach(sustain, step) {
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Release => {
+                if self.approach(0.0, self.rate * seconds) {
+                    self.stage = Stage::Done;
+                }
+            }
+        }
+        self.level
+    }
+
+    // Moves the level `step` towards `target` from either side, returns
+    // whether it got there.
+    fn approach(&mut self, target: f32, step: f32) -> bool {
+        if (target - self.level).abs() <= step {
+            self.level = target;
+            true
+        } else {
+            self.level += step.copysign(target - self.level);
+            false
+        }
+    }
+}
+
+// Change per second to cover `distance` in `seconds`, a stage of no length
+// happens within one sample.
+fn rate(distance: f32, seconds: f32) -> f32 {
+    if seconds > 0.0 {
+        distance.abs() / seconds
+    } else {
+        f32::INFINITY
+    }
+}
@@ -0,0 +1,211 @@
+//! Rolling frame time statistics.
+//!
+//! `run_game_loop` records every frame here instead of printing it. The
+//! numbers cover the last `window` frames, optionally every frame is kept
+//! as well so the whole run can be written out as CSV and compared between
+//! builds.
+
+use crate::timing::FrameTime;
+
+/// One recorded frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameSample {
+    /// Index of the frame since recording started.
+    pub frame: u64,
+    pub seconds: f32,
+    pub cycles: u64,
+    /// The frame's work did not fit into its vsync interval.
+    pub missed_vsync: bool,
+}
+
+/// Summary of the frames in the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameSummary {
+    pub frames: usize,
+    pub min_ms: f32,
+    pub max_ms: f32,
+    pub mean_ms: f32,
+    pub p50_ms: f32,
+    pub p90_ms: f32,
+    pub p99_ms: f32,
+    pub mean_mega_cycles: f32,
+    pub missed_vsyncs: usize,
+}
+
+impl std::fmt::Display for FrameSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ms/f min:{:.2} mean:{:.2} p50:{:.2} p90:{:.2} p99:{:.2} \
+             max:{:.2},\t fps:{:.2},\t mc/f:{:.2},\t missed:{}/{}",
+            self.min_ms,
+            self.mean_ms,
+            self.p50_ms,
+            self.p90_ms,
+            self.p99_ms,
+            self.max_ms,
+            1000.0 / self.mean_ms.max(f32::EPSILON),
+            self.mean_mega_cycles,
+            self.missed_vsyncs,
+            self.frames
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameStats {
+    window: usize,
+    // Ring of the last `window` frames, `next` is where the next one goes.
+    recent: Vec<FrameSample>,
+    next: usize,
+    frames: u64,
+    missed_vsyncs: u64,
+    history: Option<Vec<FrameSample>>,
+}
+
+impl FrameStats {
+    /// Keeps statistics over the last `window` frames.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "the frame window must not be empty");
+        Self {
+            window,
+            recent: Vec::with_capacity(window),
+            next: 0,
+            frames: 0,
+            missed_vsyncs: 0,
+            history: None,
+        }
+    }
+
+    /// Also keeps every frame for `write_csv`.
+    pub fn with_history(mut self) -> Self {
+        self.history = Some(Vec::new());
+        self
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Frames recorded in total.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Missed vsyncs in total.
+    pub fn missed_vsyncs(&self) -> u64 {
+        self.missed_vsyncs
+    }
+
+    pub fn record(&mut self, time: FrameTime, missed_vsync: bool) {
+        let sample = FrameSample {
+            frame: self.frames,
+            seconds: time.seconds,
+            cycles: time.cycles,
+            missed_vsync,
+        };
+        if self.recent.len() < self.window {
+            self.recent.push(sample);
+        } else {
+            self.recent[self.next] = sample;
+        }
+        self.next = (self.next + 1) % self.window;
+        self.frames += 1;
+        self.missed_vsyncs += missed_vsync as u64;
+        if let Some(history) = self.history.as_mut() {
+            history.push(sample);
+        }
+    }
+
+    /// The frames in the window, oldest first.
+    pub fn recent(&self) -> impl Iterator<Item = &FrameSample> {
+        let (newer, older) = self.recent.split_at(self.next);
+        older.iter().chain(newer.iter())
+    }
+
+    fn sorted_ms(&self) -> Vec<f32> {
+        let mut ms: Vec<f32> = self
+            .recent
+            .iter()
+            .map(|frame| 1000.0 * frame.seconds)
+            .collect();
+        ms.sort_by(f32::total_cmp);
+        ms
+    }
+
+    /// Frame time in milliseconds that `percent` of the frames in the
+    /// window stay at or below, using the nearest rank.
+    pub fn percentile(&self, percent: f32) -> Option<f32> {
+        nearest_rank(&self.sorted_ms(), percent)
+    }
+
+    pub fn summary(&self) -> Option<FrameSummary> {
+        let ms = self.sorted_ms();
+        let count = ms.len();
+        if count == 0 {
+            return None;
+        }
+        let cycles: u64 = self.recent.iter().map(|frame| frame.cycles).sum();
+        Some(FrameSummary {
+            frames: count,
+            min_ms: ms[0],
+            max_ms: ms[count - 1],
+            mean_ms: ms.iter().sum::<f32>() / count as f32,
+            p50_ms: nearest_rank(&ms, 50.0)?,
+            p90_ms: nearest_rank(&ms, 90.0)?,
+            p99_ms: nearest_rank(&ms, 99.0)?,
+            mean_mega_cycles: cycles as f32 / count as f32 / 1_000_000.0,
+            missed_vsyncs: self
+                .recent
+                .iter()
+                .filter(|frame| frame.missed_vsync)
+                .count(),
+        })
+    }
+
+    /// Counts the frames in the window per `bucket_ms` wide bucket of frame
+    /// time, the last bucket also holds every longer frame.
+    pub fn histogram(&self, bucket_ms: f32, buckets: usize) -> Vec<usize> {
+        let mut counts = vec![0; buckets];
+        if buckets == 0 {
+            return counts;
+        }
+        for frame in self.recent.iter() {
+            let bucket = (1000.0 * frame.seconds / bucket_ms) as usize;
+            counts[bucket.min(buckets - 1)] += 1;
+        }
+        counts
+    }
+
+    /// Writes every frame kept by `with_history`, or the window without it,
+    /// as `frame,ms,mega_cycles,missed_vsync` rows.
+    pub fn write_csv<W: std::io::Write>(
+        &self,
+        mut writer: W,
+    ) -> std::io::Result<()> {
+        writeln!(writer, "frame,ms,mega_cycles,missed_vsync")?;
+        let mut write = |frame: &FrameSample| {
+            writeln!(
+                writer,
+                "{},{:.4},{:.4},{}",
+                frame.frame,
+                1000.0 * frame.seconds,
+                frame.cycles as f64 / 1_000_000.0,
+                frame.missed_vsync as u8
+            )
+        };
+        match &self.history {
+            Some(history) => history.iter().try_for_each(&mut write)?,
+            None => self.recent().try_for_each(&mut write)?,
+        }
+        writer.flush()
+    }
+}
+
+fn nearest_rank(sorted: &[f32], percent: f32) -> Option<f32> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent.clamp(0.0, 100.0) / 100.0 * sorted.len() as f32).ceil();
+    Some(sorted[(rank as usize).clamp(1, sorted.len()) - 1])
+}
@@ -0,0 +1,75 @@
+//! C ABI entry points into the game layer.
+//!
+//! The crate also builds as a `cdylib` exporting these symbols, so a
+//! platform can load the game from a shared library and swap in a rebuilt
+//! one between frames. Everything the game keeps lives in `GameMemory`,
+//! which the platform owns, so it survives the swap. The platform and the
+//! library have to be built from the same source for the layouts of the
+//! types passed across to agree.
+
+use crate::{GameAudioBuffer, GameInput, GameMemory, GameScreenBuffer};
+
+/// Name of the exported `GameUpdateAndRender`.
+pub const UPDATE_AND_RENDER_SYMBOL: &[u8] = b"game_update_and_render\0";
+/// Name of the exported `GameGetSoundSamples`.
+pub const GET_SOUND_SAMPLES_SYMBOL: &[u8] = b"game_get_sound_samples\0";
+
+pub type GameUpdateAndRender = unsafe extern "C" fn(
+    memory: *mut GameMemory,
+    input: *const GameInput,
+    video_buffer: *mut GameScreenBuffer,
+);
+
+pub type GameGetSoundSamples = for<'a> unsafe extern "C" fn(
+    memory: *mut GameMemory,
+    audio_buffer: *mut GameAudioBuffer<'a>,
+);
+
+/// The game's entry points, by default the ones linked into the platform.
+#[derive(Clone, Copy)]
+pub struct GameCode {
+    pub update_and_render: GameUpdateAndRender,
+    pub get_sound_samples: GameGetSoundSamples,
+}
+
+impl Default for GameCode {
+    fn default() -> Self {
+        Self {
+            update_and_render: game_update_and_render,
+            get_sound_samples: game_get_sound_samples,
+        }
+    }
+}
+
+impl std::fmt::Debug for GameCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GameCode")
+            .field("update_and_render", &(self.update_and_render as usize))
+            .field("get_sound_samples", &(self.get_sound_samples as usize))
+            .finish()
+    }
+}
+
+/// # Safety
+///
+/// Every pointer must be valid for the duration of the call and not be
+/// accessed through anything else meanwhile.
+#[no_mangle]
+pub unsafe extern "C" fn game_update_and_render(
+    memory: *mut GameMemory,
+    input: *const GameInput,
+    video_buffer: *mut GameScreenBuffer,
+) {
+    crate::game_update_and_render(&mut *memory, &*input, &mut *video_buffer);
+}
+
+/// # Safety
+///
+/// As for `game_update_and_render`.
+#[no_mangle]
+pub unsafe extern "C" fn game_get_sound_samples(
+    memory: *mut GameMemory,
+    audio_buffer: *mut GameAudioBuffer,
+) {
+    crate::game_get_sound_samples(&mut *memory, &mut *audio_buffer);
+}
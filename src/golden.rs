@@ -0,0 +1,284 @@
+//! Golden image checks for rendered frames.
+//!
+//! Frames are stored as binary PPM (`P6`) files so they can be opened with
+//! most image viewers without pulling in an encoder. A frame matches its
+//! golden image when no colour channel of any pixel differs by more than the
+//! tolerance; on a mismatch the actual frame and a diff image are written
+//! next to each other so the failure can be inspected.
+
+use crate::GameScreenBuffer;
+use std::path::{Path, PathBuf};
+
+/// Set to regenerate golden images instead of comparing against them.
+pub const UPDATE_ENV_VAR: &str = "UPDATE_GOLDEN";
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(PathBuf, std::io::Error),
+    InvalidPpm(PathBuf, String),
+    Missing(PathBuf),
+    SizeMismatch {
+        expected: (i32, i32),
+        actual: (i32, i32),
+    },
+    Mismatch {
+        mismatched_pixels: usize,
+        max_channel_difference: u8,
+        actual: PathBuf,
+        diff: PathBuf,
+    },
+}
+
+impl std::fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GoldenError::Io(path, error) => {
+                write!(f, "{}: {}", path.display(), error)
+            }
+            GoldenError::InvalidPpm(path, reason) => {
+                write!(f, "{}: invalid PPM, {}", path.display(), reason)
+            }
+            GoldenError::Missing(path) => write!(
+                f,
+                "{} does not exist, run with {}=1 to create it",
+                path.display(),
+                UPDATE_ENV_VAR
+            ),
+            GoldenError::SizeMismatch { expected, actual } => write!(
+                f,
+                "expected a {}x{} frame, rendered {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            GoldenError::Mismatch {
+                mismatched_pixels,
+                max_channel_difference,
+                actual,
+                diff,
+            } => write!(
+                f,
+                "{} pixels differ (by up to {}), see {} and {}",
+                mismatched_pixels,
+                max_channel_difference,
+                actual.display(),
+                diff.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+/// Encodes the visible pixels of `buffer` as a binary PPM.
+pub fn encode_ppm(buffer: &GameScreenBuffer) -> Vec<u8> {
+    let mut data = format!("P6\n{} {}\n255\n", buffer.width(), buffer.height())
+        .into_bytes();
+    data.reserve(buffer.width() as usize * buffer.height() as usize * 3);
+    for y in 0..buffer.height() {
+        for &pixel in buffer.row(y) {
+            data.extend_from_slice(&[
+                (pixel >> 16) as u8,
+                (pixel >> 8) as u8,
+                pixel as u8,
+            ]);
+        }
+    }
+    data
+}
+
+fn next_token<'a>(
+    data: &'a [u8],
+    position: &mut usize,
+) -> Result<&'a [u8], String> {
+    loop {
+        match data.get(*position) {
+            Some(b'#') => {
+                while data.get(*position).is_some_and(|&c| c != b'\n') {
+                    *position += 1;
+                }
+            }
+            Some(c) if c.is_ascii_whitespace() => *position += 1,
+            Some(_) => break,
+            None => return Err("header ends early".into()),
+        }
+    }
+    let start = *position;
+    while data
+        .get(*position)
+        .is_some_and(|c| !c.is_ascii_whitespace())
+    {
+        *position += 1;
+    }
+    Ok(&data[start..*position])
+}
+
+fn next_number(
+    data: &[u8],
+    position: &mut usize,
+    name: &str,
+) -> Result<i32, String> {
+    std::str::from_utf8(next_token(data, position)?)
+        .ok()
+        .and_then(|token| token.parse().ok())
+        .filter(|&value: &i32| value >= 0)
+        .ok_or_else(|| format!("bad {}", name))
+}
+
+/// Decodes a binary PPM with a maximum value of 255.
+pub fn decode_ppm(data: &[u8]) -> Result<GameScreenBuffer, String> {
+    let mut position = 0;
+    if next_token(data, &mut position)? != b"P6" {
+        return Err("only binary (P6) files are supported".into());
+    }
+    let width = next_number(data, &mut position, "width")?;
+    let height = next_number(data, &mut position, "height")?;
+    if next_number(data, &mut position, "maximum value")? != 255 {
+        return Err("only a maximum value of 255 is supported".into());
+    }
+    // A single whitespace character separates the header from the pixels.
+    let pixels = &data[(position + 1).min(data.len())..];
+    let count = width as usize * height as usize;
+    if pixels.len() < count * 3 {
+        return Err("pixel data ends early".into());
+    }
+    let pixels = pixels
+        .chunks_exact(3)
+        .take(count)
+        .map(|rgb| u32::from_be_bytes([0, rgb[0], rgb[1], rgb[2]]))
+        .collect();
+    Ok(GameScreenBuffer::from_vec(pixels, width, height))
+}
+
+/// Result of comparing two frames channel by channel.
+pub struct Comparison {
+    pub mismatched_pixels: usize,
+    pub max_channel_difference: u8,
+    /// Mismatched pixels in red over a dimmed copy of the expected frame.
+    pub diff: GameScreenBuffer,
+}
+
+/// Compares the visible pixels of two equally sized frames, a pixel
+/// mismatches when any channel differs by more than `tolerance`.
+pub fn compare(
+    actual: &GameScreenBuffer,
+    expected: &GameScreenBuffer,
+    tolerance: u8,
+) -> Result<Comparison, GoldenError> {
+    if (actual.width(), actual.height())
+        != (expected.width(), expected.height())
+    {
+        return Err(GoldenError::SizeMismatch {
+            expected: (expected.width(), expected.height()),
+            actual: (actual.width(), actual.height()),
+        });
+    }
+
+    let mut diff = GameScreenBuffer::from_vec(
+        vec![0; actual.width() as usize * actual.height() as usize],
+        actual.width(),
+        actual.height(),
+    );
+    let mut mismatched_pixels = 0;
+    let mut max_channel_difference = 0;
+    for y in 0..actual.height() {
+        let rows = actual.row(y).iter().zip(expected.row(y));
+        for (out, (&a, &e)) in diff.row_mut(y).iter_mut().zip(rows) {
+            let difference = [16, 8, 0]
+                .iter()
+                .map(|shift| {
+                    ((a >> shift) as u8 as i32 - (e >> shift) as u8 as i32)
+                        .unsigned_abs() as u8
+                })
+                .max()
+                .unwrap_or(0);
+            max_channel_difference = max_channel_difference.max(difference);
+            *out = if difference > tolerance {
+                mismatched_pixels += 1;
+                0x00ff_0000
+            } else {
+                (e >> 2) & 0x003f_3f3f
+            };
+        }
+    }
+    Ok(Comparison {
+        mismatched_pixels,
+        max_channel_difference,
+        diff,
+    })
+}
+
+/// A directory of golden images and where to put the output of failed
+/// comparisons.
+pub struct GoldenImages {
+    pub golden_dir: PathBuf,
+    pub failure_dir: PathBuf,
+    pub tolerance: u8,
+    /// Overwrite golden images instead of comparing, defaults to whether
+    /// `UPDATE_GOLDEN` is set.
+    pub update: bool,
+}
+
+impl GoldenImages {
+    pub fn new<P: Into<PathBuf>, Q: Into<PathBuf>>(
+        golden_dir: P,
+        failure_dir: Q,
+        tolerance: u8,
+    ) -> Self {
+        Self {
+            golden_dir: golden_dir.into(),
+            failure_dir: failure_dir.into(),
+            tolerance,
+            update: std::env::var_os(UPDATE_ENV_VAR).is_some(),
+        }
+    }
+
+    pub fn golden_path(&self, name: &str) -> PathBuf {
+        self.golden_dir.join(format!("{}.ppm", name))
+    }
+
+    /// Checks `actual` against the golden image `name`, or replaces the
+    /// golden image in update mode.
+    pub fn check(
+        &self,
+        name: &str,
+        actual: &GameScreenBuffer,
+    ) -> Result<(), GoldenError> {
+        let golden_path = self.golden_path(name);
+        if self.update {
+            return write(&golden_path, &encode_ppm(actual));
+        }
+
+        let data = match std::fs::read(&golden_path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Err(GoldenError::Missing(golden_path))
+            }
+            Err(error) => return Err(GoldenError::Io(golden_path, error)),
+        };
+        let expected = decode_ppm(&data)
+            .map_err(|reason| GoldenError::InvalidPpm(golden_path, reason))?;
+        let comparison = compare(actual, &expected, self.tolerance)?;
+        if comparison.mismatched_pixels == 0 {
+            return Ok(());
+        }
+
+        let actual_path = self.failure_dir.join(format!("{}.actual.ppm", name));
+        let diff_path = self.failure_dir.join(format!("{}.diff.ppm", name));
+        write(&actual_path, &encode_ppm(actual))?;
+        write(&diff_path, &encode_ppm(&comparison.diff))?;
+        Err(GoldenError::Mismatch {
+            mismatched_pixels: comparison.mismatched_pixels,
+            max_channel_difference: comparison.max_channel_difference,
+            actual: actual_path,
+            diff: diff_path,
+        })
+    }
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<(), GoldenError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|error| GoldenError::Io(parent.to_path_buf(), error))?;
+    }
+    std::fs::write(path, contents)
+        .map_err(|error| GoldenError::Io(path.to_path_buf(), error))
+}
@@ -0,0 +1,184 @@
+//! Platform independent controller and keyboard input.
+//!
+//! The platform fills one `GameInput` per frame. Buttons record how often
+//! they changed state during the frame as well as where they ended, so a
+//! press and release between two frames is not lost. Sticks record where
+//! they started and ended and how far they went.
+
+/// The keyboard plus four gamepads.
+pub const MAX_CONTROLLERS: usize = 5;
+
+/// Index of the keyboard in `GameInput::controllers`.
+pub const KEYBOARD_CONTROLLER: usize = 0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GameButtonState {
+    /// How many times the button went up or down this frame.
+    pub half_transition_count: u32,
+    pub ended_down: bool,
+}
+
+impl GameButtonState {
+    /// Records the button's current state, counting a transition when it
+    /// changed.
+    pub fn process(&mut self, is_down: bool) {
+        if self.ended_down != is_down {
+            self.ended_down = is_down;
+            self.half_transition_count += 1;
+        }
+    }
+
+    /// Whether the button went down at least once this frame.
+    pub fn was_pressed(&self) -> bool {
+        self.half_transition_count > 1
+            || (self.half_transition_count == 1 && self.ended_down)
+    }
+}
+
+/// An analog stick, axes range from -1.0 to 1.0 with up and right positive.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GameStickState {
+    pub start_x: f32,
+    pub start_y: f32,
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+    pub end_x: f32,
+    pub end_y: f32,
+}
+
+impl GameStickState {
+    /// Records a reading taken during the frame.
+    pub fn process(&mut self, x: f32, y: f32) {
+        self.end_x = x;
+        self.end_y = y;
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+
+    pub fn average_x(&self) -> f32 {
+        (self.start_x + self.end_x) / 2.0
+    }
+
+    pub fn average_y(&self) -> f32 {
+        (self.start_y + self.end_y) / 2.0
+    }
+
+    fn next_frame(&self) -> Self {
+        Self {
+            start_x: self.end_x,
+            start_y: self.end_y,
+            min_x: self.end_x,
+            min_y: self.end_y,
+            max_x: self.end_x,
+            max_y: self.end_y,
+            end_x: self.end_x,
+            end_y: self.end_y,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GameControllerInput {
+    pub is_connected: bool,
+    pub is_analog: bool,
+
+    pub left_stick: GameStickState,
+    pub right_stick: GameStickState,
+
+    pub move_up: GameButtonState,
+    pub move_down: GameButtonState,
+    pub move_left: GameButtonState,
+    pub move_right: GameButtonState,
+
+    pub action_up: GameButtonState,
+    pub action_down: GameButtonState,
+    pub action_left: GameButtonState,
+    pub action_right: GameButtonState,
+
+    pub left_shoulder: GameButtonState,
+    pub right_shoulder: GameButtonState,
+
+    pub start: GameButtonState,
+    pub back: GameButtonState,
+}
+
+impl GameControllerInput {
+    pub fn buttons(&self) -> [&GameButtonState; 12] {
+        [
+            &self.move_up,
+            &self.move_down,
+            &self.move_left,
+            &self.move_right,
+            &self.action_up,
+            &self.action_down,
+            &self.action_left,
+            &self.action_right,
+            &self.left_shoulder,
+            &self.right_shoulder,
+            &self.start,
+            &self.back,
+        ]
+    }
+
+    pub fn buttons_mut(&mut self) -> [&mut GameButtonState; 12] {
+        [
+            &mut self.move_up,
+            &mut self.move_down,
+            &mut self.move_left,
+            &mut self.move_right,
+            &mut self.action_up,
+            &mut self.action_down,
+            &mut self.action_left,
+            &mut self.action_right,
+            &mut self.left_shoulder,
+            &mut self.right_shoulder,
+            &mut self.start,
+            &mut self.back,
+        ]
+    }
+
+    /// The state this controller starts the next frame in: buttons stay
+    /// down without any transitions, sticks start where they ended.
+    pub fn next_frame(&self) -> Self {
+        let mut next = *self;
+        for button in next.buttons_mut() {
+            button.half_transition_count = 0;
+        }
+        next.left_stick = self.left_stick.next_frame();
+        next.right_stick = self.right_stick.next_frame();
+        next
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GameInput {
+    pub controllers: [GameControllerInput; MAX_CONTROLLERS],
+}
+
+impl GameInput {
+    pub fn keyboard(&self) -> &GameControllerInput {
+        &self.controllers[KEYBOARD_CONTROLLER]
+    }
+
+    pub fn keyboard_mut(&mut self) -> &mut GameControllerInput {
+        &mut self.controllers[KEYBOARD_CONTROLLER]
+    }
+
+    /// Gamepad `index`, counting from 0 and not including the keyboard.
+    pub fn gamepad_mut(&mut self, index: usize) -> &mut GameControllerInput {
+        &mut self.controllers[KEYBOARD_CONTROLLER + 1 + index]
+    }
+
+    /// The input the platform starts filling in for the next frame.
+    pub fn next_frame(&self) -> Self {
+        let mut next = *self;
+        for controller in next.controllers.iter_mut() {
+            *controller = controller.next_frame();
+        }
+        next
+    }
+}
@@ -1,58 +1,851 @@
 use core::ffi::c_void;
 
+use crate::replay::Encode;
+
+pub mod audio_ring;
+pub mod bitmap;
+mod bytes;
+pub mod envelope;
+pub mod frame_stats;
+pub mod game_code;
+pub mod golden;
+pub mod input;
+pub mod midi;
+pub mod mixer;
+pub mod oscillator;
+pub mod platform;
+pub mod render;
+pub mod replay;
+pub mod sequencer;
+pub mod timing;
+pub mod wav;
+
+pub use frame_stats::FrameStats;
+pub use game_code::GameCode;
+pub use input::GameInput;
+pub use platform::{run_game_loop, Platform};
+
 pub struct GameScreenBuffer {
-    pub memory: *mut c_void,
-    pub width: i32,
-    pub height: i32,
-    pub bytes_per_pixel: usize,
+    // Private so safe code can't point the accessors outside the pixels.
+    memory: *mut c_void,
+    width: i32,
+    height: i32,
+    pitch: usize,
+    bytes_per_pixel: usize,
+    // Keeps the pixels alive when the buffer was built from a `Vec`, `memory`
+    // points into it.
+    owned: Option<Vec<u32>>,
 }
 
 impl GameScreenBuffer {
-    pub fn new(
+    /// # Safety
+    ///
+    /// `memory` must point to `pitch * height` bytes that are valid for reads
+    /// and writes, aligned for `u32` and not accessed through anything else
+    /// for as long as the returned buffer is alive. `pitch` must be a multiple
+    /// of `bytes_per_pixel` and at least `width * bytes_per_pixel`.
+    pub unsafe fn new(
         memory: *mut c_void,
         bytes_per_pixel: usize,
         height: i32,
         width: i32,
+        pitch: usize,
     ) -> Self {
         Self {
             memory,
             width,
             height,
+            pitch,
             bytes_per_pixel,
+            owned: None,
+        }
+    }
+
+    /// Builds a buffer over tightly packed rows of `width` pixels.
+    pub fn from_vec(pixels: Vec<u32>, width: i32, height: i32) -> Self {
+        let pitch = width as usize * core::mem::size_of::<u32>();
+        Self::from_vec_with_pitch(pixels, width, height, pitch)
+    }
+
+    /// Builds a buffer whose rows are `pitch` bytes apart.
+    pub fn from_vec_with_pitch(
+        mut pixels: Vec<u32>,
+        width: i32,
+        height: i32,
+        pitch: usize,
+    ) -> Self {
+        let bytes_per_pixel = core::mem::size_of::<u32>();
+        assert!(width >= 0 && height >= 0, "negative buffer dimensions");
+        assert!(
+            pitch.is_multiple_of(bytes_per_pixel)
+                && pitch >= width as usize * bytes_per_pixel,
+            "pitch {} does not fit rows of {} pixels",
+            pitch,
+            width
+        );
+        assert!(
+            pixels.len() >= pitch / bytes_per_pixel * height as usize,
+            "{} pixels is too small for {} rows of pitch {}",
+            pixels.len(),
+            height,
+            pitch
+        );
+        Self {
+            memory: pixels.as_mut_ptr().cast(),
+            width,
+            height,
+            pitch,
+            bytes_per_pixel,
+            owned: Some(pixels),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// Bytes from the start of one row to the start of the next, rows may be
+    /// padded past `width * bytes_per_pixel`.
+    pub fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.bytes_per_pixel
+    }
+
+    /// Pixels per row including padding.
+    pub fn stride(&self) -> usize {
+        self.pitch / self.bytes_per_pixel
+    }
+
+    /// Every row of the buffer including padding, `stride()` pixels apart.
+    pub fn pixels(&self) -> &[u32] {
+        let len = self.stride() * self.height.max(0) as usize;
+        if len == 0 {
+            return &[];
         }
+        unsafe { core::slice::from_raw_parts(self.memory.cast(), len) }
+    }
+
+    /// Every row of the buffer including padding, `stride()` pixels apart.
+    pub fn pixels_mut(&mut self) -> &mut [u32] {
+        let len = self.stride() * self.height.max(0) as usize;
+        if len == 0 {
+            return &mut [];
+        }
+        unsafe { core::slice::from_raw_parts_mut(self.memory.cast(), len) }
+    }
+
+    /// The `width` visible pixels of row `y`.
+    ///
+    /// Panics if `y` is outside the buffer.
+    pub fn row(&self, y: i32) -> &[u32] {
+        assert!(0 <= y && y < self.height, "row {} out of bounds", y);
+        let start = y as usize * self.stride();
+        &self.pixels()[start..start + self.width as usize]
+    }
+
+    /// The `width` visible pixels of row `y`.
+    ///
+    /// Panics if `y` is outside the buffer.
+    pub fn row_mut(&mut self, y: i32) -> &mut [u32] {
+        assert!(0 <= y && y < self.height, "row {} out of bounds", y);
+        let start = y as usize * self.stride();
+        let width = self.width as usize;
+        &mut self.pixels_mut()[start..start + width]
+    }
+
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<u32> {
+        if self.contains(x, y) {
+            Some(self.pixels()[y as usize * self.stride() + x as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Writes `color` at (`x`, `y`), points outside the buffer are ignored.
+    pub fn put_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if self.contains(x, y) {
+            let index = y as usize * self.stride() + x as usize;
+            self.pixels_mut()[index] = color;
+        }
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        0 <= x && x < self.width && 0 <= y && y < self.height
+    }
+
+    /// Takes back the pixels of a buffer built with `from_vec`.
+    pub fn into_vec(self) -> Option<Vec<u32>> {
+        self.owned
     }
 }
 
-pub struct GameAudioBuffer {
-    pub samples: *mut i16,
-    pub sample_count: u32,
-    pub buffer_size: u32,
-    pub bytes_per_sample: u32,
-    pub tone_volume: i16,
-    pub wave_period: u32,
-    pub time: f32,
-    pub count: u32,
+/// Where the game writes its sound, handed to `game_get_sound_samples`.
+pub struct GameAudioBuffer<'a> {
+    pub samples_per_second: u32,
+    /// Interleaved left/right samples, `sample_count` of each.
+    pub samples: &'a mut [i16],
 }
 
-impl GameAudioBuffer {
-    pub fn new(
-        buffer_size: u32,
-        bytes_per_sample: u32,
-        tone_volume: i16,
-        samples_per_second: u32,
-        freq: u32,
+impl<'a> GameAudioBuffer<'a> {
+    pub fn new(samples_per_second: u32, samples: &'a mut [i16]) -> Self {
+        Self {
+            samples_per_second,
+            samples,
+        }
+    }
+
+    /// Stereo samples to write.
+    pub fn sample_count(&self) -> usize {
+        self.samples.len() / 2
+    }
+}
+
+/// A block of memory the platform allocates once at startup and hands to the
+/// game every frame.
+///
+/// The game keeps all of its state in here rather than in globals, placing
+/// its `GameState` at the start of the permanent storage followed by the
+/// assets it refers to through `AssetSlice`s. The transient
+/// storage holds data the game can rebuild, such as caches, and does not
+/// need to survive a snapshot.
+pub struct GameMemory {
+    /// Whether the `GameState` at the start of permanent storage has been
+    /// written.
+    pub is_initialized: bool,
+    // Private so safe code can't point the storage slices elsewhere.
+    permanent_storage_size: usize,
+    permanent_storage: *mut u8,
+    transient_storage_size: usize,
+    transient_storage: *mut u8,
+    // Layout the block was allocated with when it came from `allocate`, it
+    // is freed with the same layout on drop.
+    owned: Option<std::alloc::Layout>,
+}
+
+impl GameMemory {
+    /// Alignment of blocks from `allocate`, platforms handing out their own
+    /// memory should use at least this.
+    pub const ALIGNMENT: usize = 4096;
+
+    /// Splits the block at `base` into permanent storage followed by
+    /// transient storage.
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to `permanent_storage_size + transient_storage_size`
+    /// bytes that are valid for reads and writes, aligned to
+    /// `GameMemory::ALIGNMENT` and not accessed through anything else for as
+    /// long as the returned memory is alive.
+    pub unsafe fn new(
+        base: *mut u8,
+        permanent_storage_size: usize,
+        transient_storage_size: usize,
     ) -> Self {
         Self {
-            samples: core::ptr::null_mut(),
-            buffer_size,
-            sample_count: 0,
-            bytes_per_sample,
-            tone_volume,
-            wave_period: samples_per_second / freq,
-            time: 0.0,
-            count: 0,
+            is_initialized: false,
+            permanent_storage_size,
+            permanent_storage: base,
+            transient_storage_size,
+            transient_storage: base.add(permanent_storage_size),
+            owned: None,
+        }
+    }
+
+    /// Allocates a zeroed block from the global allocator, which is freed
+    /// again when the memory is dropped.
+    pub fn allocate(
+        permanent_storage_size: usize,
+        transient_storage_size: usize,
+    ) -> Self {
+        let size = permanent_storage_size
+            .checked_add(transient_storage_size)
+            .expect("game memory size overflows usize");
+        let layout =
+            std::alloc::Layout::from_size_align(size.max(1), Self::ALIGNMENT)
+                .expect("game memory size overflows isize");
+        // SAFETY: the layout is never zero sized.
+        let base = unsafe { std::alloc::alloc_zeroed(layout) };
+        if base.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        // SAFETY: the block was just allocated with room for both storages
+        // and is only reachable through the returned memory.
+        let mut memory = unsafe {
+            Self::new(base, permanent_storage_size, transient_storage_size)
+        };
+        memory.owned = Some(layout);
+        memory
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.permanent_storage_size + self.transient_storage_size
+    }
+
+    pub fn permanent_storage_size(&self) -> usize {
+        self.permanent_storage_size
+    }
+
+    pub fn transient_storage_size(&self) -> usize {
+        self.transient_storage_size
+    }
+
+    pub fn permanent_storage(&self) -> &[u8] {
+        storage(self.permanent_storage, self.permanent_storage_size)
+    }
+
+    pub fn permanent_storage_mut(&mut self) -> &mut [u8] {
+        storage_mut(self.permanent_storage, self.permanent_storage_size)
+    }
+
+    pub fn transient_storage(&self) -> &[u8] {
+        storage(self.transient_storage, self.transient_storage_size)
+    }
+
+    pub fn transient_storage_mut(&mut self) -> &mut [u8] {
+        storage_mut(self.transient_storage, self.transient_storage_size)
+    }
+
+    /// An arena over all of transient storage, starting out empty.
+    pub fn transient_arena(&mut self) -> MemoryArena<'_> {
+        MemoryArena::new(self.transient_storage_mut())
+    }
+
+    /// The game state at the start of permanent storage, written with its
+    /// default the first time it is asked for.
+    ///
+    /// The state is never dropped, the platform releases the block as a
+    /// whole.
+    ///
+    /// # Panics
+    ///
+    /// If permanent storage is too small or not aligned for a `GameState`.
+    pub fn state(&mut self) -> &mut GameState {
+        self.split_permanent_storage().0
+    }
+
+    /// The game state if it has been written, leaving memory untouched
+    /// otherwise.
+    ///
+    /// # Panics
+    ///
+    /// As for `state`.
+    pub fn initialized_state(&self) -> Option<&GameState> {
+        if !self.is_initialized {
+            return None;
+        }
+        let state = self.permanent_storage();
+        assert!(
+            state.len() >= core::mem::size_of::<GameState>(),
+            "permanent storage of {} bytes cannot hold the {} byte game state",
+            state.len(),
+            core::mem::size_of::<GameState>()
+        );
+        let state = state.as_ptr().cast::<GameState>();
+        assert!(
+            state.align_offset(core::mem::align_of::<GameState>()) == 0,
+            "permanent storage is not aligned for the game state"
+        );
+        // SAFETY: as in `split_permanent_storage`, and `is_initialized` says
+        // the state has been written.
+        Some(unsafe { &*state })
+    }
+
+    /// The game state along with the assets stored after it.
+    ///
+    /// # Panics
+    ///
+    /// As for `state`.
+    pub fn state_and_assets(&mut self) -> (&mut GameState, Assets<'_>) {
+        let (state, storage) = self.split_permanent_storage();
+        (state, Assets { storage })
+    }
+
+    /// The values pushed with `push_asset`.
+    pub fn assets(&self) -> Assets<'_> {
+        let storage = self.permanent_storage();
+        Assets {
+            storage: storage
+                .get(core::mem::size_of::<GameState>()..)
+                .unwrap_or(&[]),
+        }
+    }
+
+    /// Copies `values` into permanent storage after the game state, where
+    /// they are kept along with it, snapshots included. Nothing pushed is
+    /// ever freed.
+    pub fn push_asset<T: Copy>(
+        &mut self,
+        values: &[T],
+    ) -> Result<AssetSlice<T>, ArenaError> {
+        let (state, storage) = self.split_permanent_storage();
+        let arena = MemoryArena::new(storage);
+        arena.push_bytes(state.asset_bytes, 1)?;
+        let offset =
+            arena.push_slice(values)?.as_ptr() as usize - arena.base as usize;
+        state.asset_bytes = arena.used();
+        Ok(AssetSlice {
+            offset,
+            len: values.len(),
+            _values: core::marker::PhantomData,
+        })
+    }
+
+    /// Copies `sound` into permanent storage and makes it playable through
+    /// the mixer. `None` once `MAX_SOUNDS` are added or storage is full.
+    pub fn add_sound(
+        &mut self,
+        sound: &wav::LoadedSound,
+    ) -> Option<mixer::SoundId> {
+        let index = self.state().sounds.iter().position(Option::is_none)?;
+        let sound = sound.store(self).ok()?;
+        self.state().sounds[index] = Some(sound);
+        Some(mixer::SoundId(index as u32))
+    }
+
+    /// Copies `wavetable` into permanent storage and makes it playable by
+    /// `Waveform::Wavetable` oscillators. `None` once `MAX_WAVETABLES` are
+    /// added or storage is full.
+    pub fn add_wavetable(
+        &mut self,
+        wavetable: &oscillator::Wavetable,
+    ) -> Option<oscillator::WavetableId> {
+        let index = self.state().wavetables.iter().position(Option::is_none)?;
+        let wavetable = wavetable.store(self).ok()?;
+        self.state().wavetables[index] = Some(wavetable);
+        Some(oscillator::WavetableId(index as u32))
+    }
+
+    // The game state, written with its default the first time, and the
+    // rest of permanent storage.
+    fn split_permanent_storage(&mut self) -> (&mut GameState, &mut [u8]) {
+        assert!(
+            self.permanent_storage_size >= core::mem::size_of::<GameState>(),
+            "permanent storage of {} bytes cannot hold the {} byte game state",
+            self.permanent_storage_size,
+            core::mem::size_of::<GameState>()
+        );
+        let (state, rest) =
+            storage_mut(self.permanent_storage, self.permanent_storage_size)
+                .split_at_mut(core::mem::size_of::<GameState>());
+        let state = state.as_mut_ptr().cast::<GameState>();
+        assert!(
+            state.align_offset(core::mem::align_of::<GameState>()) == 0,
+            "permanent storage is not aligned for the game state"
+        );
+        // SAFETY: the checks above and the contract of `new` make `state`
+        // valid for a `GameState`, which is written before it is first read.
+        unsafe {
+            if !self.is_initialized {
+                state.write(GameState::default());
+                self.is_initialized = true;
+            }
+            (&mut *state, rest)
+        }
+    }
+}
+
+impl Drop for GameMemory {
+    fn drop(&mut self) {
+        if let Some(layout) = self.owned {
+            // SAFETY: `allocate` got the block from the global allocator
+            // with this layout.
+            unsafe { std::alloc::dealloc(self.permanent_storage, layout) };
+        }
+    }
+}
+
+fn storage<'a>(base: *mut u8, size: usize) -> &'a [u8] {
+    if size == 0 {
+        return &[];
+    }
+    // SAFETY: `GameMemory::new` requires `size` bytes at `base`.
+    unsafe { core::slice::from_raw_parts(base, size) }
+}
+
+fn storage_mut<'a>(base: *mut u8, size: usize) -> &'a mut [u8] {
+    if size == 0 {
+        return &mut [];
+    }
+    // SAFETY: as for `storage`, `GameMemory` hands the slice out through a
+    // mutable borrow of itself.
+    unsafe { core::slice::from_raw_parts_mut(base, size) }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArenaError {
+    /// The arena had `remaining` bytes left but the allocation needed
+    /// `requested`, alignment padding included.
+    OutOfMemory { requested: usize, remaining: usize },
+    /// Alignments have to be a power of two.
+    InvalidAlignment(usize),
+}
+
+impl std::fmt::Display for ArenaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArenaError::OutOfMemory {
+                requested,
+                remaining,
+            } => write!(
+                f,
+                "arena out of memory, {} bytes requested with {} left",
+                requested, remaining
+            ),
+            ArenaError::InvalidAlignment(alignment) => {
+                write!(f, "alignment {} is not a power of two", alignment)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArenaError {}
+
+/// A linear allocator over a borrowed block of memory.
+///
+/// Allocations are bumped off the front of the block and only handed back
+/// all at once, when a `TemporaryMemory` scope ends or the arena goes away.
+/// Values pushed into an arena are never dropped.
+#[derive(Debug)]
+pub struct MemoryArena<'a> {
+    base: *mut u8,
+    size: usize,
+    used: core::cell::Cell<usize>,
+    _storage: core::marker::PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> MemoryArena<'a> {
+    pub fn new(storage: &'a mut [u8]) -> Self {
+        Self {
+            base: storage.as_mut_ptr(),
+            size: storage.len(),
+            used: core::cell::Cell::new(0),
+            _storage: core::marker::PhantomData,
         }
     }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.size - self.used.get()
+    }
+
+    // Reserves `size` bytes at an address aligned to `alignment`.
+    fn push_raw(
+        &self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<*mut u8, ArenaError> {
+        if !alignment.is_power_of_two() {
+            return Err(ArenaError::InvalidAlignment(alignment));
+        }
+        let used = self.used.get();
+        let remaining = self.size - used;
+        let padding =
+            (self.base as usize + used).wrapping_neg() & (alignment - 1);
+        let requested = padding.saturating_add(size);
+        if requested > remaining {
+            return Err(ArenaError::OutOfMemory {
+                requested,
+                remaining,
+            });
+        }
+        self.used.set(used + requested);
+        // SAFETY: `used + padding` is within the block checked above.
+        Ok(unsafe { self.base.add(used + padding) })
+    }
+
+    /// `size` bytes at an address aligned to `alignment`, holding whatever
+    /// the memory held before.
+    // Every push hands out a region no other push overlaps, rolling the
+    // arena back needs `&mut self` so nothing handed out can outlive that.
+    #[allow(clippy::mut_from_ref)]
+    pub fn push_bytes(
+        &self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<&mut [u8], ArenaError> {
+        let bytes = self.push_raw(size, alignment)?;
+        // SAFETY: `push_raw` reserved `size` bytes of initialized memory.
+        Ok(unsafe { core::slice::from_raw_parts_mut(bytes, size) })
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn push_struct<T>(&self, value: T) -> Result<&mut T, ArenaError> {
+        let memory = self
+            .push_raw(core::mem::size_of::<T>(), core::mem::align_of::<T>())?
+            .cast::<T>();
+        // SAFETY: the memory is reserved, aligned and large enough for `T`.
+        unsafe {
+            memory.write(value);
+            Ok(&mut *memory)
+        }
+    }
+
+    /// `count` default initialized values.
+    #[allow(clippy::mut_from_ref)]
+    pub fn push_array<T: Default>(
+        &self,
+        count: usize,
+    ) -> Result<&mut [T], ArenaError> {
+        let memory = self.push_array_raw::<T>(count)?;
+        // SAFETY: every element is written before the slice is made.
+        unsafe {
+            for i in 0..count {
+                memory.add(i).write(T::default());
+            }
+            Ok(core::slice::from_raw_parts_mut(memory, count))
+        }
+    }
+
+    /// A copy of `values`.
+    #[allow(clippy::mut_from_ref)]
+    pub fn push_slice<T: Copy>(
+        &self,
+        values: &[T],
+    ) -> Result<&mut [T], ArenaError> {
+        let memory = self.push_array_raw::<T>(values.len())?;
+        // SAFETY: the reserved memory has room for every value and cannot
+        // overlap `values`, which lives outside of any unreserved region.
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                values.as_ptr(),
+                memory,
+                values.len(),
+            );
+            Ok(core::slice::from_raw_parts_mut(memory, values.len()))
+        }
+    }
+
+    fn push_array_raw<T>(&self, count: usize) -> Result<*mut T, ArenaError> {
+        let size = core::mem::size_of::<T>().saturating_mul(count);
+        Ok(self.push_raw(size, core::mem::align_of::<T>())?.cast())
+    }
+
+    /// Carves `size` bytes out of this arena for a separate arena, which
+    /// keeps them until this arena is rolled back.
+    pub fn sub_arena(
+        &self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<MemoryArena<'_>, ArenaError> {
+        Ok(MemoryArena::new(self.push_bytes(size, alignment)?))
+    }
+
+    /// Starts a scope whose allocations are given back when the returned
+    /// `TemporaryMemory` ends or is dropped.
+    pub fn begin_temporary_memory(&mut self) -> TemporaryMemory<'_, 'a> {
+        TemporaryMemory {
+            used: self.used.get(),
+            arena: self,
+        }
+    }
+}
+
+/// An arena scope that rolls the arena back to where it began on drop.
+///
+/// It derefs to the arena, everything pushed through it is borrowed from
+/// the scope and so cannot be used once the scope ends. There is no
+/// mutable deref, swapping the arena out from under the scope would roll
+/// back a different arena on drop.
+pub struct TemporaryMemory<'t, 'a> {
+    arena: &'t mut MemoryArena<'a>,
+    used: usize,
+}
+
+impl<'t, 'a> TemporaryMemory<'t, 'a> {
+    /// Starts a nested scope, rolled back before this one is.
+    pub fn begin_temporary_memory(&mut self) -> TemporaryMemory<'_, 'a> {
+        self.arena.begin_temporary_memory()
+    }
+
+    pub fn end(self) {}
+}
+
+impl<'t, 'a> core::ops::Deref for TemporaryMemory<'t, 'a> {
+    type Target = MemoryArena<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.arena
+    }
+}
+
+impl<'t, 'a> Drop for TemporaryMemory<'t, 'a> {
+    fn drop(&mut self) {
+        self.arena.used.set(self.used);
+    }
+}
+
+/// Values pushed into game memory with `GameMemory::push_asset`.
+///
+/// The slice holds the offset of its values in permanent storage rather
+/// than their address, so game state keeping one can be copied out of
+/// permanent storage and back, or into another process, and still find
+/// them. `Assets::get` hands out the values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetSlice<T> {
+    offset: usize,
+    len: usize,
+    _values: core::marker::PhantomData<fn() -> T>,
+}
+
+impl<T> Encode for AssetSlice<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.offset.encode(out);
+        self.len.encode(out);
+    }
+}
+
+impl<T> AssetSlice<T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// The assets of a game memory, borrowed from its permanent storage.
+#[derive(Debug, Clone, Copy)]
+pub struct Assets<'a> {
+    storage: &'a [u8],
+}
+
+impl<'a> Assets<'a> {
+    /// The values of `slice`.
+    ///
+    /// # Panics
+    ///
+    /// If `slice` reaches past the assets, which only happens to slices
+    /// pushed into a different game memory.
+    pub fn get<T: Copy>(&self, slice: AssetSlice<T>) -> &'a [T] {
+        if slice.len == 0 {
+            return &[];
+        }
+        let bytes = core::mem::size_of::<T>()
+            .checked_mul(slice.len)
+            .and_then(|size| slice.offset.checked_add(size))
+            .and_then(|end| self.storage.get(slice.offset..end))
+            .expect("asset slice out of bounds");
+        assert!(
+            bytes.as_ptr().align_offset(core::mem::align_of::<T>()) == 0,
+            "asset slice not aligned"
+        );
+        // SAFETY: `push_asset` copied `slice.len` values of `T` here, the
+        // bounds and alignment are checked above.
+        unsafe { core::slice::from_raw_parts(bytes.as_ptr().cast(), slice.len) }
+    }
+}
+
+/// Sounds that can be added with `GameMemory::add_sound`.
+pub const MAX_SOUNDS: usize = 32;
+/// Wavetables that can be added with `GameMemory::add_wavetable`.
+pub const MAX_WAVETABLES: usize = 16;
+
+/// Everything the game keeps from one frame to the next.
+///
+/// Snapshots and replay files copy the state in and out of permanent storage
+/// byte for byte, possibly into another process, so it must not hold
+/// pointers or references. Being `Copy` keeps out `Vec`s, `Box`es and the
+/// like; data of any size goes into permanent storage after the state
+/// through `GameMemory::push_asset` and is kept as `AssetSlice`s.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GameState {
+    pub x_offset: i32,
+    pub y_offset: i32,
+    pub mixer: mixer::Mixer,
+    pub tone: Option<mixer::VoiceId>,
+    /// Sounds the mixer's `SoundId`s index into, their samples are kept in
+    /// permanent storage after the state.
+    pub sounds: [Option<wav::StoredSound>; MAX_SOUNDS],
+    /// Wavetables the oscillators' `WavetableId`s index into, kept in
+    /// permanent storage like `sounds`.
+    pub wavetables: [Option<oscillator::StoredWavetable>; MAX_WAVETABLES],
+    /// Background music, played through `mixer`.
+    pub music: Option<Music>,
+    // Bytes of permanent storage after the state taken by assets.
+    asset_bytes: usize,
+}
+
+impl Encode for GameState {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.x_offset.encode(out);
+        self.y_offset.encode(out);
+        self.mixer.encode(out);
+        self.tone.encode(out);
+        self.sounds.encode(out);
+        self.wavetables.encode(out);
+        self.music.encode(out);
+        self.asset_bytes.encode(out);
+    }
+}
+
+/// Music the game can play in the background.
+// Both live in the game state, which can't hold a box for the player.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy)]
+pub enum Music {
+    Song(sequencer::Sequencer),
+    Midi(midi::MidiPlayer),
+}
+
+impl Encode for Music {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Music::Song(sequencer) => {
+                out.push(0);
+                sequencer.encode(out);
+            }
+            Music::Midi(player) => {
+                out.push(1);
+                player.encode(out);
+            }
+        }
+    }
+}
+
+impl Music {
+    /// Schedules the notes within the next `frame_count` frames mixed at
+    /// `samples_per_second` on `mixer`.
+    pub fn render(
+        &mut self,
+        assets: Assets,
+        mixer: &mut mixer::Mixer,
+        frame_count: usize,
+        samples_per_second: u32,
+    ) {
+        match self {
+            Music::Song(sequencer) => {
+                sequencer.render(assets, mixer, frame_count, samples_per_second)
+            }
+            Music::Midi(player) => {
+                player.render(assets, mixer, frame_count, samples_per_second)
+            }
+        }
+    }
+}
+
+impl GameState {
+    pub fn play_sound(
+        &mut self,
+        sound: mixer::SoundId,
+        volume: f32,
+    ) -> Option<mixer::VoiceId> {
+        self.mixer.play(mixer::Voice::sound(sound, volume, 0.0))
+    }
 }
 
 fn render_weird_gradient(
@@ -60,46 +853,89 @@ fn render_weird_gradient(
     x_offset: i32,
     y_offset: i32,
 ) {
-    let pixel = buffer.memory.cast::<u32>();
-    for x in 0..buffer.width {
-        for y in 0..buffer.height {
-            let index = (x + buffer.width * y) as usize;
-            let red = unsafe { pixel.add(index).cast::<u8>().add(2) };
-            let green = unsafe { pixel.add(index).cast::<u8>().add(1) };
-            let blue = unsafe { pixel.add(index).cast::<u8>().add(0) };
-            unsafe {
-                blue.write((x + x_offset) as u8);
-                green.write((y + y_offset) as u8);
-                red.write(0);
-            }
+    for y in 0..buffer.height() {
+        let row = buffer.row_mut(y);
+        for (x, pixel) in (0..).zip(row.iter_mut()) {
+            let blue = (x + x_offset) as u8 as u32;
+            let green = (y + y_offset) as u8 as u32;
+            *pixel = (green << 8) | blue;
         }
     }
 }
 
-pub fn fill_audio_buffer(audio_buffer: &mut GameAudioBuffer) {
-    let mut sample_out = audio_buffer.samples;
-    let mut time = 0f32;
-    for _ in 0..audio_buffer.sample_count {
-        let value = (time.sin() * audio_buffer.tone_volume as f32) as i16;
-        unsafe {
-            sample_out.write(value);
-            sample_out = sample_out.add(1);
-            sample_out.write(value);
-            sample_out = sample_out.add(1);
+pub fn game_update_and_render(
+    memory: &mut GameMemory,
+    input: &input::GameInput,
+    video_buffer: &mut GameScreenBuffer,
+) {
+    let state = memory.state();
+    for controller in input.controllers.iter().filter(|c| c.is_connected) {
+        if controller.is_analog {
+            state.x_offset += (8.0 * controller.left_stick.average_x()) as i32;
+            state.y_offset += (8.0 * controller.left_stick.average_y()) as i32;
+        }
+        if controller.move_left.ended_down {
+            state.x_offset -= 4;
+        }
+        if controller.move_right.ended_down {
+            state.x_offset += 4;
+        }
+        if controller.move_up.ended_down {
+            state.y_offset += 4;
+        }
+        if controller.move_down.ended_down {
+            state.y_offset -= 4;
         }
-        time = 2.0
-            * std::f32::consts::PI
-            * (audio_buffer.count as f32 / audio_buffer.wave_period as f32);
-        audio_buffer.count += 1;
     }
+
+    render_weird_gradient(video_buffer, state.x_offset, state.y_offset);
 }
 
-pub fn game_update_and_render(
-    video_buffer: &mut GameScreenBuffer,
+// The tone the game plays, bent by up to 256Hz with `y_offset`.
+const TONE_HZ: f32 = 251.0;
+const TONE_VOLUME: f32 = 1500.0 / i16::MAX as f32;
+
+/// Fills `audio_buffer` with the next `sample_count` samples of the game's
+/// sound, picking up where the previous call stopped.
+///
+/// Nothing here depends on how often it is called, the platform may ask for
+/// any number of samples at a time independently of the frame rate.
+pub fn game_get_sound_samples(
+    memory: &mut GameMemory,
     audio_buffer: &mut GameAudioBuffer,
-    x_offset: i32,
-    y_offset: i32,
 ) {
-    render_weird_gradient(video_buffer, x_offset, y_offset);
-    fill_audio_buffer(audio_buffer);
+    let (state, assets) = memory.state_and_assets();
+    // Views of the assets on the stack, the slots without one play silence.
+    let stored_sounds = &state.sounds;
+    let sounds: [wav::LoadedSound; MAX_SOUNDS] =
+        core::array::from_fn(|index| {
+            stored_sounds[index]
+                .map_or_else(Default::default, |sound| sound.load(assets))
+        });
+    let stored_wavetables = &state.wavetables;
+    let wavetables: [oscillator::Wavetable; MAX_WAVETABLES] =
+        core::array::from_fn(|index| {
+            stored_wavetables[index]
+                .map_or_else(Default::default, |table| table.load(assets))
+        });
+    let frequency =
+        TONE_HZ + 256.0 * (state.y_offset as f32 / 1000.0).clamp(-1.0, 1.0);
+    let volume = TONE_VOLUME;
+    let mixer = &mut state.mixer;
+    if !state.tone.is_some_and(|tone| mixer.is_playing(tone)) {
+        state.tone = mixer.play(mixer::Voice::tone(frequency, volume, 0.0));
+    }
+    if let Some(tone) = state.tone.and_then(|tone| mixer.voice_mut(tone)) {
+        tone.frequency = frequency;
+        tone.volume = volume;
+    }
+    if let Some(music) = &mut state.music {
+        music.render(
+            assets,
+            mixer,
+            audio_buffer.sample_count(),
+            audio_buffer.samples_per_second,
+        );
+    }
+    mixer.mix(audio_buffer, &sounds, &wavetables);
 }
@@ -0,0 +1,596 @@
+//! Standard MIDI File loading and playback.
+//!
+//! Format 0 and 1 files are read into one list of events in the order they
+//! play. Only notes, program changes and tempo changes are kept.
+//! `MidiFile::store` copies the events into game memory, and a
+//! `MidiPlayer` plays them from there through the mixer with the instruments a
+//! `ProgramMap` picks for each MIDI program. Like `Sequencer::render` it
+//! schedules the events falling within the frames about to be mixed. Event
+//! times are worked out in whole numbers from the start of the file, so the
+//! same file always renders to the same samples, however they are asked for.
+
+use crate::bytes::{read_u16_be, read_u32_be, Truncated};
+use crate::envelope::Adsr;
+use crate::mixer::{Mixer, VoiceId, MAX_VOICES};
+use crate::oscillator::Waveform;
+use crate::replay::Encode;
+use crate::sequencer::{note_frequency, release, Instrument};
+use crate::{ArenaError, AssetSlice, Assets, GameMemory};
+
+/// 120 beats per minute, until the file sets a tempo.
+const DEFAULT_MICROSECONDS_PER_QUARTER: u32 = 500_000;
+/// General MIDI keeps channel 10 for percussion.
+const PERCUSSION_CHANNEL: u8 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        key: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    /// Microseconds per quarter note.
+    Tempo(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidiEvent {
+    /// Ticks from the start of the file.
+    pub tick: u64,
+    pub message: MidiMessage,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiFile {
+    pub format: u16,
+    pub ticks_per_quarter: u16,
+    /// The events of every track, ordered by tick. Events on the same tick
+    /// keep the order of their tracks, then their order within the track.
+    pub events: Vec<MidiEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MidiError {
+    NotAMidiFile,
+    Truncated,
+    UnsupportedFormat(u16),
+    /// SMPTE time codes instead of ticks per quarter note.
+    UnsupportedDivision(u16),
+    InvalidVariableLength,
+    /// A data byte where the track has no running status to apply it to.
+    MissingStatus,
+}
+
+impl std::fmt::Display for MidiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MidiError::NotAMidiFile => write!(f, "missing MThd header"),
+            MidiError::Truncated => write!(f, "file ends inside a chunk"),
+            MidiError::UnsupportedFormat(format) => {
+                write!(f, "unsupported format {}", format)
+            }
+            MidiError::UnsupportedDivision(division) => {
+                write!(f, "unsupported time division {:#x}", division)
+            }
+            MidiError::InvalidVariableLength => {
+                write!(f, "variable length quantity longer than 4 bytes")
+            }
+            MidiError::MissingStatus => {
+                write!(f, "data byte without a running status")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MidiError {}
+
+impl From<Truncated> for MidiError {
+    fn from(_: Truncated) -> Self {
+        MidiError::Truncated
+    }
+}
+
+// Reads the events of one track chunk.
+struct TrackReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> TrackReader<'a> {
+    fn byte(&mut self) -> Result<u8, MidiError> {
+        let byte = *self.data.get(self.offset).ok_or(MidiError::Truncated)?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], MidiError> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + count)
+            .ok_or(MidiError::Truncated)?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn variable_length(&mut self) -> Result<u32, MidiError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MidiError::InvalidVariableLength)
+    }
+
+    fn read_events(
+        mut self,
+        events: &mut Vec<MidiEvent>,
+    ) -> Result<(), MidiError> {
+        let mut tick = 0u64;
+        let mut running_status = None;
+        while self.offset < self.data.len() {
+            tick += self.variable_length()? as u64;
+            let byte = self.byte()?;
+            let (status, data) = if byte & 0x80 != 0 {
+                (byte, None)
+            } else {
+                (running_status.ok_or(MidiError::MissingStatus)?, Some(byte))
+            };
+
+            let message = match status {
+                0xff => {
+                    running_status = None;
+                    let kind = self.byte()?;
+                    let length = self.variable_length()? as usize;
+                    match (kind, self.bytes(length)?) {
+                        // End of track.
+                        (0x2f, _) => return Ok(()),
+                        (0x51, &[a, b, c, ..]) => {
+                            Some(MidiMessage::Tempo(u32::from_be_bytes([
+                                0, a, b, c,
+                            ])))
+                        }
+                        _ => None,
+                    }
+                }
+                0xf0 | 0xf7 => {
+                    running_status = None;
+                    let length = self.variable_length()? as usize;
+                    self.bytes(length)?;
+                    None
+                }
+                // System common and real time messages don't belong in
+                // files.
+                0xf1..=0xfe => None,
+                _ => {
+                    running_status = Some(status);
+                    let channel = status & 0x0f;
+                    let first = match data {
+                        Some(first) => first,
+                        None => self.byte()?,
+                    };
+                    let second = match status >> 4 {
+                        0xc | 0xd => 0,
+                        _ => self.byte()?,
+                    };
+                    match status >> 4 {
+                        0x9 if second > 0 => Some(MidiMessage::NoteOn {
+                            channel,
+                            key: first,
+                            velocity: second,
+                        }),
+                        // A note on without velocity releases the note.
+                        0x8 | 0x9 => Some(MidiMessage::NoteOff {
+                            channel,
+                            key: first,
+                        }),
+                        0xc => Some(MidiMessage::ProgramChange {
+                            channel,
+                            program: first,
+                        }),
+                        _ => None,
+                    }
+                }
+            };
+            if let Some(message) = message {
+                events.push(MidiEvent { tick, message });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MidiFile {
+    /// Decodes a format 0 or 1 Standard MIDI File timed in ticks per
+    /// quarter note.
+    pub fn from_smf(data: &[u8]) -> Result<Self, MidiError> {
+        if data.get(0..4) != Some(b"MThd") {
+            return Err(MidiError::NotAMidiFile);
+        }
+        let header_size = read_u32_be(data, 4)? as usize;
+        let format = read_u16_be(data, 8)?;
+        let track_count = read_u16_be(data, 10)?;
+        let division = read_u16_be(data, 12)?;
+        if format > 1 {
+            return Err(MidiError::UnsupportedFormat(format));
+        }
+        if division & 0x8000 != 0 || division == 0 {
+            return Err(MidiError::UnsupportedDivision(division));
+        }
+
+        let mut events = Vec::new();
+        let mut tracks_read = 0;
+        let mut offset = 8 + header_size;
+        while tracks_read < track_count {
+            let id =
+                data.get(offset..offset + 4).ok_or(MidiError::Truncated)?;
+            let size = read_u32_be(data, offset + 4)? as usize;
+            let body = offset + 8;
+            let chunk =
+                data.get(body..body + size).ok_or(MidiError::Truncated)?;
+            // Other chunk types are for extensions and are skipped.
+            if id == b"MTrk" {
+                TrackReader {
+                    data: chunk,
+                    offset: 0,
+                }
+                .read_events(&mut events)?;
+                tracks_read += 1;
+            }
+            offset = body + size;
+        }
+        // Stable, so events on the same tick stay in track order.
+        events.sort_by_key(|event| event.tick);
+        Ok(Self {
+            format,
+            ticks_per_quarter: division,
+            events,
+        })
+    }
+}
+
+impl MidiFile {
+    /// Copies the events into the assets of `memory`.
+    pub fn store(
+        &self,
+        memory: &mut GameMemory,
+    ) -> Result<StoredMidiFile, ArenaError> {
+        Ok(StoredMidiFile {
+            format: self.format,
+            ticks_per_quarter: self.ticks_per_quarter,
+            events: memory.push_asset(&self.events)?,
+        })
+    }
+}
+
+/// A file copied into game memory by `MidiFile::store`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoredMidiFile {
+    pub format: u16,
+    pub ticks_per_quarter: u16,
+    pub events: AssetSlice<MidiEvent>,
+}
+
+impl Encode for StoredMidiFile {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.format.encode(out);
+        self.ticks_per_quarter.encode(out);
+        self.events.encode(out);
+    }
+}
+
+/// Which instrument plays each MIDI program.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgramMap {
+    programs: [Option<Instrument>; 128],
+    /// Plays the programs without an instrument of their own, they are
+    /// silent without one.
+    pub fallback: Option<Instrument>,
+    /// Plays channel 10 whatever its program.
+    pub percussion: Option<Instrument>,
+}
+
+fn synth(
+    waveform: Waveform,
+    attack: f32,
+    decay: f32,
+    sustain: f32,
+    release: f32,
+) -> Instrument {
+    Instrument::Synth {
+        waveform,
+        adsr: Adsr::new(attack, decay, sustain, release),
+        volume: 0.2,
+    }
+}
+
+impl Encode for ProgramMap {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.programs.encode(out);
+        self.fallback.encode(out);
+        self.percussion.encode(out);
+    }
+}
+
+impl Default for ProgramMap {
+    fn default() -> Self {
+        Self {
+            programs: [None; 128],
+            fallback: None,
+            percussion: None,
+        }
+    }
+}
+
+impl ProgramMap {
+    /// A rough synthesized stand in for every family of eight General MIDI
+    /// programs and for percussion.
+    pub fn general_midi() -> Self {
+        let mut map = Self::default();
+        for program in 0..128u8 {
+            let instrument = match program / 8 {
+                // Piano.
+                0 => synth(Waveform::Triangle, 0.005, 0.8, 0.2, 0.3),
+                // Chromatic percussion.
+                1 => synth(Waveform::Sine, 0.001, 0.4, 0.0, 0.3),
+                // Organ.
+                2 => synth(
+                    Waveform::Square { pulse_width: 0.5 },
+                    0.01,
+                    0.0,
+                    1.0,
+                    0.05,
+                ),
+                // Guitar.
+                3 => synth(Waveform::Saw, 0.005, 0.5, 0.3, 0.2),
+                // Bass.
+                4 => synth(Waveform::Triangle, 0.005, 0.3, 0.6, 0.1),
+                // Strings and ensembles.
+                5 | 6 => synth(Waveform::Saw, 0.1, 0.2, 0.8, 0.4),
+                // Brass.
+                7 => synth(Waveform::Saw, 0.03, 0.1, 0.8, 0.15),
+                // Reed.
+                8 => synth(
+                    Waveform::Square { pulse_width: 0.3 },
+                    0.03,
+                    0.1,
+                    0.8,
+                    0.1,
+                ),
+                // Pipe.
+                9 => synth(Waveform::Sine, 0.05, 0.1, 0.9, 0.15),
+                // Synth lead.
+                10 => synth(
+                    Waveform::Square { pulse_width: 0.25 },
+                    0.005,
+                    0.1,
+                    0.8,
+                    0.1,
+                ),
+                // Synth pad.
+                11 => synth(Waveform::Triangle, 0.3, 0.3, 0.8, 0.8),
+                _ => synth(Waveform::Saw, 0.01, 0.3, 0.5, 0.2),
+            };
+            map.set(program, instrument);
+        }
+        map.percussion =
+            Some(synth(Waveform::WhiteNoise, 0.001, 0.15, 0.0, 0.05));
+        map
+    }
+
+    /// Plays `program`, which is below 128, with `instrument`.
+    pub fn set(&mut self, program: u8, instrument: Instrument) {
+        self.programs[program as usize] = Some(instrument);
+    }
+
+    /// The instrument playing `program` on zero based `channel`.
+    pub fn instrument(&self, channel: u8, program: u8) -> Option<&Instrument> {
+        if channel == PERCUSSION_CHANNEL {
+            return self.percussion.as_ref();
+        }
+        self.programs
+            .get(program as usize)
+            .and_then(Option::as_ref)
+            .or(self.fallback.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MidiPlayer {
+    file: StoredMidiFile,
+    pub programs: ProgramMap,
+    next_event: usize,
+    frame: u64,
+    // Tempo changes restart the count of time from the tick they are on,
+    // `origin_time` is its time in microseconds times `ticks_per_quarter`,
+    // which keeps it whole.
+    origin_tick: u64,
+    origin_time: u64,
+    microseconds_per_quarter: u32,
+    channel_programs: [u8; 16],
+    // Voices of the notes held down, by channel and key. There can't be
+    // more than the mixer has voices, slots of notes whose voice was taken
+    // over are reused.
+    notes: [Option<(u8, u8, VoiceId)>; MAX_VOICES],
+}
+
+impl Encode for MidiPlayer {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.file.encode(out);
+        self.programs.encode(out);
+        self.next_event.encode(out);
+        self.frame.encode(out);
+        self.origin_tick.encode(out);
+        self.origin_time.encode(out);
+        self.microseconds_per_quarter.encode(out);
+        self.channel_programs.encode(out);
+        self.notes.encode(out);
+    }
+}
+
+impl MidiPlayer {
+    pub fn new(file: StoredMidiFile, programs: ProgramMap) -> Self {
+        Self {
+            file,
+            programs,
+            next_event: 0,
+            frame: 0,
+            origin_tick: 0,
+            origin_time: 0,
+            microseconds_per_quarter: DEFAULT_MICROSECONDS_PER_QUARTER,
+            channel_programs: [0; 16],
+            notes: [None; MAX_VOICES],
+        }
+    }
+
+    pub fn file(&self) -> &StoredMidiFile {
+        &self.file
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.next_event < self.file.events.len()
+    }
+
+    /// Frames rendered so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Releases every note and stops.
+    pub fn stop(&mut self, mixer: &mut Mixer) {
+        for (_, _, voice) in self.notes.iter_mut().filter_map(Option::take) {
+            release(mixer, voice, 0);
+        }
+        self.next_event = self.file.events.len();
+    }
+
+    /// Schedules the events within the next `frame_count` frames mixed at
+    /// `samples_per_second` and moves past them. `assets` are those of the
+    /// memory the file was stored in.
+    pub fn render(
+        &mut self,
+        assets: Assets,
+        mixer: &mut Mixer,
+        frame_count: usize,
+        samples_per_second: u32,
+    ) {
+        let events = assets.get(self.file.events);
+        let end = self.frame + frame_count as u64;
+        while let Some(&event) = events.get(self.next_event) {
+            // Events too far in to be timed end the song.
+            let time = self.tick_time(event.tick);
+            let start =
+                time.and_then(|time| self.time_frame(time, samples_per_second));
+            let (time, start) = match (time, start) {
+                (Some(time), Some(start)) => (time, start),
+                _ => {
+                    self.stop(mixer);
+                    break;
+                }
+            };
+            if start >= end {
+                break;
+            }
+            let offset = start.saturating_sub(self.frame) as u32;
+            self.play_event(mixer, event, time, offset);
+            self.next_event += 1;
+        }
+        self.frame = end;
+    }
+
+    // `None` once the time no longer fits in 64 bits.
+    fn tick_time(&self, tick: u64) -> Option<u64> {
+        (tick - self.origin_tick)
+            .checked_mul(self.microseconds_per_quarter as u64)?
+            .checked_add(self.origin_time)
+    }
+
+    fn time_frame(&self, time: u64, samples_per_second: u32) -> Option<u64> {
+        let time = time as u128 * samples_per_second as u128;
+        let scale = self.file.ticks_per_quarter as u128 * 1_000_000;
+        std::convert::TryFrom::try_from((time + scale / 2) / scale).ok()
+    }
+
+    // `time` is that of the tick of `event`, as `tick_time` works it out.
+    fn play_event(
+        &mut self,
+        mixer: &mut Mixer,
+        event: MidiEvent,
+        time: u64,
+        offset: u32,
+    ) {
+        match event.message {
+            MidiMessage::Tempo(microseconds_per_quarter) => {
+                self.origin_time = time;
+                self.origin_tick = event.tick;
+                self.microseconds_per_quarter = microseconds_per_quarter;
+            }
+            MidiMessage::ProgramChange { channel, program } => {
+                self.channel_programs[channel as usize] = program;
+            }
+            MidiMessage::NoteOn {
+                channel,
+                key,
+                velocity,
+            } => {
+                self.release(mixer, channel, key, offset);
+                let program = self.channel_programs[channel as usize];
+                let instrument =
+                    match self.programs.instrument(channel, program) {
+                        Some(instrument) => instrument,
+                        None => return,
+                    };
+                let frequency = note_frequency(key);
+                // Dropped when every voice is busy.
+                if let Some(voice) = mixer.play(instrument.voice(frequency)) {
+                    let velocity = velocity as f32 / 127.0;
+                    if !mixer.note_on(voice, offset, frequency, velocity) {
+                        // The note queue is full, the voice would never
+                        // sound.
+                        mixer.stop(voice);
+                        return;
+                    }
+                    let slot = self.notes.iter().position(|note| {
+                        note.is_none_or(|(_, _, voice)| {
+                            !mixer.is_playing(voice)
+                        })
+                    });
+                    if let Some(slot) = slot {
+                        self.notes[slot] = Some((channel, key, voice));
+                    }
+                }
+            }
+            MidiMessage::NoteOff { channel, key } => {
+                self.release(mixer, channel, key, offset);
+            }
+        }
+    }
+
+    fn release(
+        &mut self,
+        mixer: &mut Mixer,
+        channel: u8,
+        key: u8,
+        offset: u32,
+    ) {
+        if let Some(note) = self
+            .notes
+            .iter_mut()
+            .find(|note| note.is_some_and(|(c, k, _)| c == channel && k == key))
+        {
+            if let Some((_, _, voice)) = note.take() {
+                release(mixer, voice, offset);
+            }
+        }
+    }
+}
@@ -0,0 +1,645 @@
+//! Multi-voice software mixer.
+//!
+//! Every voice keeps its own phase accumulator so pitch changes carry on
+//! from where the waveform was instead of restarting it, and gain changes
+//! are ramped across a buffer so neither produces clicks. Voices are mixed
+//! in `f32` and clipped into the interleaved `i16` samples of a
+//! `GameAudioBuffer`.
+//!
+//! Besides the oscillators of `crate::oscillator` voices can play
+//! `LoadedSound`s, which are resampled with linear interpolation from their
+//! own rate to the rate of the buffer being mixed.
+//!
+//! Voices with an `Envelope` stay silent until a note starts, and a mix
+//! that leaves one without a note sounding or waiting frees it. Note events
+//! are scheduled a number of frames into the next mix and take effect on
+//! exactly that frame, however the mix is split into buffers.
+
+use crate::envelope::{Adsr, Envelope, Stage};
+use crate::oscillator::{Oscillator, Waveform, Wavetable};
+use crate::replay::Encode;
+use crate::wav::LoadedSound;
+use crate::GameAudioBuffer;
+
+pub const MAX_VOICES: usize = 16;
+/// Note events that can wait to be mixed at once.
+pub const MAX_NOTE_EVENTS: usize = 64;
+
+// Voices are summed on the stack this many frames at a time.
+const MIX_CHUNK_FRAMES: usize = 256;
+
+/// Handle to a playing voice, stays invalid once the voice stopped even if
+/// its slot gets reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoiceId {
+    index: u16,
+    generation: u16,
+}
+
+/// Index of a sound in the slice of sounds passed to `Mixer::mix`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoundId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VoiceSource {
+    /// An oscillator at the voice's `frequency`.
+    Oscillator(Oscillator),
+    /// A loaded sound played at the voice's `pitch`.
+    Sound { sound: SoundId, looping: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoteEvent {
+    /// Starts the voice's envelope peaking at `velocity` and sets the
+    /// frequency of oscillators, sounds start again from the beginning.
+    On { frequency: f32, velocity: f32 },
+    /// Releases the voice's envelope.
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScheduledNote {
+    voice: VoiceId,
+    /// Frames from the start of the next mix.
+    frame: u32,
+    event: NoteEvent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Voice {
+    pub source: VoiceSource,
+    /// Pitch of oscillators in Hz.
+    pub frequency: f32,
+    /// Playback rate of sounds, 2.0 plays an octave up.
+    pub pitch: f32,
+    /// Linear gain, 1.0 is full scale.
+    pub volume: f32,
+    /// -1.0 is hard left, 1.0 hard right.
+    pub pan: f32,
+    /// Gain over the course of each note, the voice plays at a constant
+    /// gain without one.
+    pub envelope: Option<Envelope>,
+    /// The frame of a sound being played.
+    phase: f64,
+    // Gains used at the end of the last mix, the next mix ramps from these.
+    left_gain: f32,
+    right_gain: f32,
+    stopping: bool,
+    finished: bool,
+}
+
+impl Encode for VoiceId {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.index.encode(out);
+        self.generation.encode(out);
+    }
+}
+
+impl Encode for SoundId {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+    }
+}
+
+impl Encode for VoiceSource {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            VoiceSource::Oscillator(oscillator) => {
+                out.push(0);
+                oscillator.encode(out);
+            }
+            VoiceSource::Sound { sound, looping } => {
+                out.push(1);
+                sound.encode(out);
+                looping.encode(out);
+            }
+        }
+    }
+}
+
+impl Encode for NoteEvent {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            NoteEvent::On {
+                frequency,
+                velocity,
+            } => {
+                out.push(0);
+                frequency.encode(out);
+                velocity.encode(out);
+            }
+            NoteEvent::Off => out.push(1),
+        }
+    }
+}
+
+impl Encode for ScheduledNote {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.voice.encode(out);
+        self.frame.encode(out);
+        self.event.encode(out);
+    }
+}
+
+impl Encode for Voice {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.source.encode(out);
+        self.frequency.encode(out);
+        self.pitch.encode(out);
+        self.volume.encode(out);
+        self.pan.encode(out);
+        self.envelope.encode(out);
+        self.phase.encode(out);
+        self.left_gain.encode(out);
+        self.right_gain.encode(out);
+        self.stopping.encode(out);
+        self.finished.encode(out);
+    }
+}
+
+impl Voice {
+    /// A sine wave.
+    pub fn tone(frequency: f32, volume: f32, pan: f32) -> Self {
+        Self::oscillator(Waveform::Sine, frequency, volume, pan)
+    }
+
+    pub fn oscillator(
+        waveform: Waveform,
+        frequency: f32,
+        volume: f32,
+        pan: f32,
+    ) -> Self {
+        Self {
+            source: VoiceSource::Oscillator(Oscillator::new(waveform)),
+            frequency,
+            pitch: 1.0,
+            volume,
+            pan,
+            envelope: None,
+            phase: 0.0,
+            left_gain: 0.0,
+            right_gain: 0.0,
+            stopping: false,
+            finished: false,
+        }
+    }
+
+    /// Plays `sound` once from the start.
+    pub fn sound(sound: SoundId, volume: f32, pan: f32) -> Self {
+        Self {
+            source: VoiceSource::Sound {
+                sound,
+                looping: false,
+            },
+            ..Self::tone(0.0, volume, pan)
+        }
+    }
+
+    /// Plays `sound` over and over until the voice is stopped.
+    pub fn looping_sound(sound: SoundId, volume: f32, pan: f32) -> Self {
+        Self {
+            source: VoiceSource::Sound {
+                sound,
+                looping: true,
+            },
+            ..Self::tone(0.0, volume, pan)
+        }
+    }
+
+    /// The voice shaped by `adsr`, silent until it gets a
+    /// `NoteEvent::On`. Its slot is freed once the last note has been
+    /// released and faded out.
+    pub fn with_envelope(mut self, adsr: Adsr) -> Self {
+        self.envelope = Some(Envelope::new(adsr));
+        self
+    }
+
+    fn apply(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::On {
+                frequency,
+                velocity,
+            } => {
+                self.frequency = frequency;
+                if let VoiceSource::Sound { .. } = self.source {
+                    self.phase = 0.0;
+                    self.finished = false;
+                }
+                if let Some(envelope) = &mut self.envelope {
+                    envelope.note_on(velocity);
+                }
+            }
+            NoteEvent::Off => {
+                if let Some(envelope) = &mut self.envelope {
+                    envelope.note_off();
+                }
+            }
+        }
+    }
+
+    // Whether the envelope has no note sounding, either because none has
+    // started yet or because the last one has faded out.
+    fn is_silent(&self) -> bool {
+        self.envelope.is_some_and(|envelope| {
+            matches!(envelope.stage(), Stage::Idle | Stage::Done)
+        })
+    }
+
+    fn target_gains(&self) -> (f32, f32) {
+        if self.stopping {
+            return (0.0, 0.0);
+        }
+        // Constant power panning.
+        let angle =
+            (self.pan.clamp(-1.0, 1.0) + 1.0) * core::f32::consts::FRAC_PI_4;
+        (self.volume * angle.cos(), self.volume * angle.sin())
+    }
+
+    fn next_sample(
+        &mut self,
+        samples_per_second: f32,
+        sounds: &[LoadedSound],
+        wavetables: &[Wavetable],
+    ) -> (f32, f32) {
+        if self.finished {
+            return (0.0, 0.0);
+        }
+        let gain = match &mut self.envelope {
+            Some(envelope) => match envelope.stage() {
+                // Sounds don't move on between notes.
+                Stage::Idle | Stage::Done => return (0.0, 0.0),
+                _ => envelope.next_level(samples_per_second),
+            },
+            None => 1.0,
+        };
+        let (left, right) =
+            self.source_sample(samples_per_second, sounds, wavetables);
+        (left * gain, right * gain)
+    }
+
+    fn source_sample(
+        &mut self,
+        samples_per_second: f32,
+        sounds: &[LoadedSound],
+        wavetables: &[Wavetable],
+    ) -> (f32, f32) {
+        match &mut self.source {
+            VoiceSource::Oscillator(oscillator) => {
+                let value = oscillator.next_sample(
+                    self.frequency,
+                    samples_per_second,
+                    wavetables,
+                );
+                (value, value)
+            }
+            &mut VoiceSource::Sound { sound, looping } => {
+                let sound = match sounds.get(sound.0 as usize) {
+                    Some(sound) if sound.frame_count() > 0 => sound,
+                    _ => {
+                        self.finished = true;
+                        return (0.0, 0.0);
+                    }
+                };
+                let frame_count = sound.frame_count();
+                if self.phase >= frame_count as f64 {
+                    if !looping {
+                        self.finished = true;
+                        return (0.0, 0.0);
+                    }
+                    self.phase %= frame_count as f64;
+                }
+
+                let frame = self.phase as usize;
+                let fraction = (self.phase - frame as f64) as f32;
+                let next = match frame + 1 {
+                    next if next < frame_count => Some(next),
+                    _ if looping => Some(0),
+                    _ => None,
+                };
+                let (left, right) = sound.frame(frame);
+                let (next_left, next_right) =
+                    next.map_or((0, 0), |next| sound.frame(next));
+                let lerp = |a: i16, b: i16| {
+                    (a as f32 + (b as f32 - a as f32) * fraction)
+                        / -(i16::MIN as f32)
+                };
+
+                self.phase += (sound.samples_per_second as f32 * self.pitch
+                    / samples_per_second) as f64;
+                (lerp(left, next_left), lerp(right, next_right))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct VoiceSlot {
+    generation: u16,
+    voice: Option<Voice>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Mixer {
+    slots: [VoiceSlot; MAX_VOICES],
+    // Ordered by frame, the ones scheduled for the same frame in the order
+    // they were scheduled in. All the `Some`s come first.
+    notes: [Option<ScheduledNote>; MAX_NOTE_EVENTS],
+    pub master_volume: f32,
+}
+
+impl Encode for VoiceSlot {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.generation.encode(out);
+        self.voice.encode(out);
+    }
+}
+
+impl Encode for Mixer {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.slots.encode(out);
+        self.notes.encode(out);
+        self.master_volume.encode(out);
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self {
+            slots: [VoiceSlot {
+                generation: 0,
+                voice: None,
+            }; MAX_VOICES],
+            notes: [None; MAX_NOTE_EVENTS],
+            master_volume: 1.0,
+        }
+    }
+}
+
+impl Mixer {
+    /// Starts `voice`, returns `None` when every slot is taken.
+    pub fn play(&mut self, mut voice: Voice) -> Option<VoiceId> {
+        let index = self.slots.iter().position(|slot| slot.voice.is_none())?;
+        // Sounds start at full gain so their attack isn't smeared, later
+        // changes are ramped.
+        let (left_gain, right_gain) = voice.target_gains();
+        voice.left_gain = left_gain;
+        voice.right_gain = right_gain;
+        let slot = &mut self.slots[index];
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.voice = Some(voice);
+        Some(VoiceId {
+            index: index as u16,
+            generation: slot.generation,
+        })
+    }
+
+    pub fn voice(&self, id: VoiceId) -> Option<&Voice> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.voice.as_ref())
+            .filter(|voice| !voice.stopping && !voice.finished)
+    }
+
+    /// The playing voice behind `id`, changes to frequency, volume and pan
+    /// take effect from the next mix.
+    pub fn voice_mut(&mut self, id: VoiceId) -> Option<&mut Voice> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.voice.as_mut())
+            .filter(|voice| !voice.stopping && !voice.finished)
+    }
+
+    pub fn is_playing(&self, id: VoiceId) -> bool {
+        self.voice(id).is_some()
+    }
+
+    /// Fades the voice out over the next mix and frees its slot.
+    pub fn stop(&mut self, id: VoiceId) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.stopping = true;
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        for voice in
+            self.slots.iter_mut().filter_map(|slot| slot.voice.as_mut())
+        {
+            voice.stopping = true;
+        }
+    }
+
+    /// Schedules `event` for the voice `frame` frames into the next mix,
+    /// frames past its end wait for the mixes after it. Returns `false`
+    /// when the voice isn't playing or `MAX_NOTE_EVENTS` are waiting.
+    pub fn schedule(
+        &mut self,
+        id: VoiceId,
+        frame: u32,
+        event: NoteEvent,
+    ) -> bool {
+        let count = self.queued_notes();
+        if !self.is_playing(id) || count == MAX_NOTE_EVENTS {
+            return false;
+        }
+        let at = self.notes[..count]
+            .iter()
+            .position(|note| note.is_some_and(|note| note.frame > frame))
+            .unwrap_or(count);
+        self.notes[at..=count].rotate_right(1);
+        self.notes[at] = Some(ScheduledNote {
+            voice: id,
+            frame,
+            event,
+        });
+        true
+    }
+
+    pub fn note_on(
+        &mut self,
+        id: VoiceId,
+        frame: u32,
+        frequency: f32,
+        velocity: f32,
+    ) -> bool {
+        self.schedule(
+            id,
+            frame,
+            NoteEvent::On {
+                frequency,
+                velocity,
+            },
+        )
+    }
+
+    pub fn note_off(&mut self, id: VoiceId, frame: u32) -> bool {
+        self.schedule(id, frame, NoteEvent::Off)
+    }
+
+    /// Starts `voice` and plays one note on it from `frame` for `length`
+    /// frames, for sound effects. The voice needs an envelope to be freed
+    /// once the note has faded out. Returns `None` without starting
+    /// anything when every slot is taken or there is no room for both
+    /// note events.
+    pub fn play_note(
+        &mut self,
+        voice: Voice,
+        frame: u32,
+        length: u32,
+        frequency: f32,
+        velocity: f32,
+    ) -> Option<VoiceId> {
+        if MAX_NOTE_EVENTS - self.queued_notes() < 2 {
+            return None;
+        }
+        let id = self.play(voice)?;
+        self.note_on(id, frame, frequency, velocity);
+        self.note_off(id, frame.saturating_add(length));
+        Some(id)
+    }
+
+    fn queued_notes(&self) -> usize {
+        self.notes.iter().take_while(|note| note.is_some()).count()
+    }
+
+    // Keeps the notes `keep` returns true for, in order.
+    fn retain_notes(
+        &mut self,
+        mut keep: impl FnMut(&mut ScheduledNote) -> bool,
+    ) {
+        let mut kept = 0;
+        for index in 0..MAX_NOTE_EVENTS {
+            if let Some(mut note) = self.notes[index].take() {
+                if keep(&mut note) {
+                    self.notes[kept] = Some(note);
+                    kept += 1;
+                }
+            }
+        }
+    }
+
+    pub fn active_voices(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| {
+                slot.voice
+                    .is_some_and(|voice| !voice.stopping && !voice.finished)
+            })
+            .count()
+    }
+
+    /// Overwrites the samples of `buffer` with the mix of every voice,
+    /// `sounds` and `wavetables` hold what `SoundId`s and `WavetableId`s
+    /// refer to.
+    pub fn mix(
+        &mut self,
+        buffer: &mut GameAudioBuffer,
+        sounds: &[LoadedSound],
+        wavetables: &[Wavetable],
+    ) {
+        let samples_per_second = buffer.samples_per_second as f32;
+        let output = &mut *buffer.samples;
+        let frame_count = output.len() / 2;
+        let frames = frame_count.max(1) as f32;
+
+        let mut gain_steps = [(0f32, 0f32); MAX_VOICES];
+        for (slot, step) in self.slots.iter().zip(gain_steps.iter_mut()) {
+            if let Some(voice) = &slot.voice {
+                let (left, right) = voice.target_gains();
+                *step = (
+                    (left - voice.left_gain) / frames,
+                    (right - voice.right_gain) / frames,
+                );
+            }
+        }
+
+        for (chunk_index, chunk) in
+            output.chunks_mut(MIX_CHUNK_FRAMES * 2).enumerate()
+        {
+            let chunk_start = chunk_index * MIX_CHUNK_FRAMES;
+            let mut mixed = [0f32; MIX_CHUNK_FRAMES * 2];
+            let mixed = &mut mixed[..chunk.len()];
+            for (index, (slot, &(left_step, right_step))) in
+                self.slots.iter_mut().zip(gain_steps.iter()).enumerate()
+            {
+                let voice = match slot.voice.as_mut() {
+                    Some(voice) => voice,
+                    None => continue,
+                };
+                let id = VoiceId {
+                    index: index as u16,
+                    generation: slot.generation,
+                };
+                let mut notes = self
+                    .notes
+                    .iter()
+                    .map_while(|note| note.as_ref())
+                    .filter(|note| {
+                        note.voice == id && note.frame as usize >= chunk_start
+                    })
+                    .peekable();
+                for (frame_index, frame) in
+                    mixed.chunks_exact_mut(2).enumerate()
+                {
+                    while let Some(note) = notes.next_if(|note| {
+                        note.frame as usize == chunk_start + frame_index
+                    }) {
+                        voice.apply(note.event);
+                    }
+                    let (left, right) = voice.next_sample(
+                        samples_per_second,
+                        sounds,
+                        wavetables,
+                    );
+                    voice.left_gain += left_step;
+                    voice.right_gain += right_step;
+                    frame[0] += left * voice.left_gain;
+                    frame[1] += right * voice.right_gain;
+                }
+            }
+            for (out, value) in chunk.iter_mut().zip(mixed.iter()) {
+                let scaled = value * self.master_volume * i16::MAX as f32;
+                *out = scaled.clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+            }
+        }
+
+        self.retain_notes(|note| {
+            match note.frame.checked_sub(frame_count as u32) {
+                Some(frame) => {
+                    note.frame = frame;
+                    true
+                }
+                None => false,
+            }
+        });
+        let notes = self.notes;
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if let Some(voice) = slot.voice.as_mut() {
+                let (left, right) = voice.target_gains();
+                voice.left_gain = left;
+                voice.right_gain = right;
+                let id = VoiceId {
+                    index: index as u16,
+                    generation: slot.generation,
+                };
+                let waiting = notes
+                    .iter()
+                    .map_while(|note| note.as_ref())
+                    .any(|note| note.voice == id);
+                if voice.stopping
+                    || voice.finished
+                    || (voice.is_silent() && !waiting)
+                {
+                    slot.voice = None;
+                }
+            }
+        }
+        // Notes for voices that stopped are dropped with them.
+        let slots = self.slots;
+        self.retain_notes(|note| {
+            let slot = &slots[note.voice.index as usize];
+            slot.generation == note.voice.generation && slot.voice.is_some()
+        });
+    }
+}
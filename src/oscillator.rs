@@ -0,0 +1,338 @@
+//! Band-limited oscillators.
+//!
+//! Every oscillator keeps its phase as a fraction of a cycle and advances it
+//! by `frequency / samples_per_second` per sample, so any frequency in Hz
+//! plays at any sample rate. Naive square, saw and triangle waves alias
+//! badly at high pitches; their discontinuities are smoothed with PolyBLEP
+//! and their corners with PolyBLAMP residuals instead. Wavetables are
+//! band-limited by keeping one copy of the cycle per octave with the
+//! harmonics above that octave's Nyquist limit removed.
+
+use std::borrow::Cow;
+
+use crate::replay::Encode;
+use crate::{ArenaError, AssetSlice, Assets, GameMemory};
+
+/// Index of a wavetable in the slice of wavetables passed to
+/// `Oscillator::next_sample` or `Mixer::mix`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavetableId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Sine,
+    /// High for `pulse_width` of every cycle, a square wave at 0.5.
+    Square {
+        pulse_width: f32,
+    },
+    /// Rises from -1 to 1 over a cycle.
+    Saw,
+    Triangle,
+    /// Every sample independent and uniform in [-1, 1), the frequency is
+    /// ignored.
+    WhiteNoise,
+    /// White noise filtered to fall off by 3dB per octave, the frequency is
+    /// ignored.
+    PinkNoise,
+    Wavetable(WavetableId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Oscillator {
+    pub waveform: Waveform,
+    /// Position within the current cycle in [0, 1).
+    phase: f64,
+    noise_state: u32,
+    // Paul Kellet's pink noise filter.
+    pink: [f32; 7],
+}
+
+impl Encode for WavetableId {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+    }
+}
+
+impl Encode for Waveform {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Waveform::Sine => out.push(0),
+            Waveform::Square { pulse_width } => {
+                out.push(1);
+                pulse_width.encode(out);
+            }
+            Waveform::Saw => out.push(2),
+            Waveform::Triangle => out.push(3),
+            Waveform::WhiteNoise => out.push(4),
+            Waveform::PinkNoise => out.push(5),
+            Waveform::Wavetable(id) => {
+                out.push(6);
+                id.encode(out);
+            }
+        }
+    }
+}
+
+impl Encode for Oscillator {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.waveform.encode(out);
+        self.phase.encode(out);
+        self.noise_state.encode(out);
+        self.pink.encode(out);
+    }
+}
+
+impl Oscillator {
+    pub fn new(waveform: Waveform) -> Self {
+        Self {
+            waveform,
+            phase: 0.0,
+            noise_state: 0x2545_f491,
+            pink: [0.0; 7],
+        }
+    }
+
+    /// Starts the noise waveforms from `seed` instead of the default, the
+    /// same seed always gives the same noise.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        // Xorshift never leaves zero.
+        self.noise_state = seed.max(1);
+        self
+    }
+
+    pub fn phase(&self) -> f64 {
+        self.phase
+    }
+
+    fn white_noise(&mut self) -> f32 {
+        let mut x = self.noise_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.noise_state = x;
+        (x >> 8) as f32 / (1 << 23) as f32 - 1.0
+    }
+
+    fn pink_noise(&mut self) -> f32 {
+        let white = self.white_noise();
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.055_517_9;
+        b[1] = 0.99332 * b[1] + white * 0.075_075_9;
+        b[2] = 0.969 * b[2] + white * 0.153_852;
+        b[3] = 0.8665 * b[3] + white * 0.310_485_6;
+        b[4] = 0.55 * b[4] + white * 0.532_952_2;
+        b[5] = -0.7616 * b[5] - white * 0.016_898;
+        let pink = b[..6].iter().sum::<f32>() + b[6] + white * 0.5362;
+        b[6] = white * 0.115_926;
+        pink * 0.11
+    }
+
+    /// The next sample of the waveform at `frequency` Hz, roughly in
+    /// [-1, 1]. `wavetables` holds the tables `Waveform::Wavetable` refers
+    /// to, a missing one plays silence.
+    pub fn next_sample(
+        &mut self,
+        frequency: f32,
+        samples_per_second: f32,
+        wavetables: &[Wavetable],
+    ) -> f32 {
+        let dt = (frequency / samples_per_second).abs().min(0.5) as f64;
+        let t = self.phase;
+        let value = match self.waveform {
+            Waveform::Sine => (t * core::f64::consts::TAU).sin(),
+            Waveform::Square { pulse_width } => {
+                let width = (pulse_width as f64).clamp(dt, 1.0 - dt);
+                let naive = if t < width { 1.0 } else { -1.0 };
+                naive + poly_blep(t, dt)
+                    - poly_blep((t - width).rem_euclid(1.0), dt)
+            }
+            Waveform::Saw => 2.0 * t - 1.0 - poly_blep(t, dt),
+            Waveform::Triangle => {
+                let naive = 1.0 - 4.0 * (t - 0.5).abs();
+                // The slope changes by 8 per cycle, 8 * dt per sample, at
+                // both corners.
+                naive + 4.0 * dt * poly_blamp(t, dt)
+                    - 4.0 * dt * poly_blamp((t + 0.5).rem_euclid(1.0), dt)
+            }
+            Waveform::WhiteNoise => self.white_noise() as f64,
+            Waveform::PinkNoise => self.pink_noise() as f64,
+            Waveform::Wavetable(id) => match wavetables.get(id.0 as usize) {
+                Some(table) => table.sample(t, frequency / samples_per_second),
+                None => 0.0,
+            },
+        };
+        self.phase += (frequency / samples_per_second) as f64;
+        self.phase -= self.phase.floor();
+        value as f32
+    }
+}
+
+/// Residual of a band-limited step at phase 0, `dt` is the phase advance
+/// per sample.
+fn poly_blep(t: f64, dt: f64) -> f64 {
+    if dt <= 0.0 {
+        0.0
+    } else if t < dt {
+        let t = t / dt;
+        2.0 * t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Residual of a band-limited corner at phase 0, the integral of
+/// `poly_blep`, so for a change of slope of two per sample.
+fn poly_blamp(t: f64, dt: f64) -> f64 {
+    if dt <= 0.0 {
+        0.0
+    } else if t < dt {
+        let t = t / dt - 1.0;
+        -t * t * t / 3.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt + 1.0;
+        t * t * t / 3.0
+    } else {
+        0.0
+    }
+}
+
+/// One cycle of a user defined waveform. Tables stored in game memory
+/// borrow their samples.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Wavetable<'a> {
+    cycle_len: usize,
+    // One cycle per level, level `i` holding the harmonics up to
+    // `level_harmonics(i)`, each twice as many as the one before.
+    samples: Cow<'a, [f32]>,
+}
+
+/// A wavetable copied into game memory by `Wavetable::store`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StoredWavetable {
+    cycle_len: usize,
+    samples: AssetSlice<f32>,
+}
+
+impl Encode for StoredWavetable {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.cycle_len.encode(out);
+        self.samples.encode(out);
+    }
+}
+
+impl StoredWavetable {
+    /// The table, borrowing its samples from `assets`.
+    pub fn load<'a>(&self, assets: Assets<'a>) -> Wavetable<'a> {
+        Wavetable {
+            cycle_len: self.cycle_len,
+            samples: Cow::Borrowed(assets.get(self.samples)),
+        }
+    }
+}
+
+impl Wavetable<'static> {
+    /// Builds the table from one cycle of samples, at least two.
+    pub fn new(cycle: &[f32]) -> Self {
+        let len = cycle.len();
+        assert!(len >= 2, "a wavetable needs at least two samples");
+        let cos: Vec<f64> = (0..len)
+            .map(|i| (core::f64::consts::TAU * i as f64 / len as f64).cos())
+            .collect();
+        let sin: Vec<f64> = (0..len)
+            .map(|i| (core::f64::consts::TAU * i as f64 / len as f64).sin())
+            .collect();
+        // The Nyquist bin has no phase to speak of and is left out.
+        let mean = cycle.iter().map(|&x| x as f64).sum::<f64>() / len as f64;
+        let harmonics: Vec<(f64, f64)> = (1..=(len - 1) / 2)
+            .map(|k| {
+                let (mut a, mut b) = (0.0, 0.0);
+                for (n, &x) in cycle.iter().enumerate() {
+                    let i = k * n % len;
+                    a += x as f64 * cos[i];
+                    b += x as f64 * sin[i];
+                }
+                (2.0 * a / len as f64, 2.0 * b / len as f64)
+            })
+            .collect();
+
+        let mut samples = Vec::new();
+        let mut level = 0;
+        loop {
+            let count = level_harmonics(len, level);
+            samples.extend((0..len).map(|n| {
+                let mut x = mean;
+                for (k, &(a, b)) in (1..).zip(harmonics.iter().take(count)) {
+                    let i = k * n % len;
+                    x += a * cos[i] + b * sin[i];
+                }
+                x as f32
+            }));
+            if count >= max_harmonic(len) {
+                break;
+            }
+            level += 1;
+        }
+        Self {
+            cycle_len: len,
+            samples: Cow::Owned(samples),
+        }
+    }
+}
+
+impl<'a> Wavetable<'a> {
+    /// Samples in one cycle.
+    pub fn cycle_len(&self) -> usize {
+        self.cycle_len
+    }
+
+    /// Copies the table into the assets of `memory`.
+    pub fn store(
+        &self,
+        memory: &mut GameMemory,
+    ) -> Result<StoredWavetable, ArenaError> {
+        Ok(StoredWavetable {
+            cycle_len: self.cycle_len,
+            samples: memory.push_asset(&self.samples)?,
+        })
+    }
+
+    /// The table at `phase`, keeping only the harmonics that stay below
+    /// Nyquist at `cycles_per_sample`.
+    fn sample(&self, phase: f64, cycles_per_sample: f32) -> f64 {
+        let len = self.cycle_len;
+        if len == 0 {
+            return 0.0;
+        }
+        let limit = 0.5 / cycles_per_sample.abs() as f64;
+        let level = match (0..self.samples.len() / len)
+            .rposition(|level| level_harmonics(len, level) as f64 <= limit)
+        {
+            Some(level) => &self.samples[level * len..(level + 1) * len],
+            // Even the fundamental is above Nyquist.
+            None => return 0.0,
+        };
+        let position = phase * len as f64;
+        let index = position as usize % len;
+        let next = (index + 1) % len;
+        let fraction = position - position.floor();
+        level[index] as f64
+            + (level[next] as f64 - level[index] as f64) * fraction
+    }
+}
+
+// Harmonics in the top level of a table, the bottom one keeps the
+// fundamental even when there is none below Nyquist.
+fn max_harmonic(cycle_len: usize) -> usize {
+    (cycle_len.saturating_sub(1) / 2).max(1)
+}
+
+// Harmonics kept in `level` of a table, doubling up to all of them.
+fn level_harmonics(cycle_len: usize, level: usize) -> usize {
+    1usize
+        .checked_shl(level as u32)
+        .unwrap_or(usize::MAX)
+        .min(max_harmonic(cycle_len))
+}
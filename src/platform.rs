@@ -0,0 +1,114 @@
+use crate::frame_stats::FrameStats;
+use crate::timing::{Clock, FrameTimer};
+use crate::{
+    GameAudioBuffer, GameCode, GameInput, GameMemory, GameScreenBuffer,
+};
+
+/// Everything the game loop needs from the operating system.
+///
+/// A backend only implements this trait, `run_game_loop` takes care of
+/// calling into the game layer and measuring frame times.
+pub trait Platform {
+    /// Pumps OS messages and records them and the controllers in `input`,
+    /// which starts out as the previous frame's input carried over by
+    /// `GameInput::next_frame`. Returns `false` once the platform wants the
+    /// game loop to stop.
+    fn poll_input(&mut self, input: &mut GameInput) -> bool;
+
+    /// The memory block the game keeps its state in, allocated once when the
+    /// platform starts.
+    fn game_memory(&mut self) -> &mut GameMemory;
+
+    /// The game entry points to call this frame, platforms that reload the
+    /// game while it runs swap them here between frames.
+    fn game_code(&mut self) -> GameCode {
+        GameCode::default()
+    }
+
+    /// The back buffer the game renders the next frame into.
+    fn back_buffer(&mut self) -> GameScreenBuffer;
+
+    /// The rate the sound device plays samples at.
+    fn samples_per_second(&self) -> u32;
+
+    /// How many stereo samples the sound device needs this frame.
+    fn sound_samples_needed(&mut self) -> u32;
+
+    /// Hands the interleaved samples the game wrote to the sound device.
+    fn submit_audio(&mut self, samples: &[i16]);
+
+    /// Holds the frame until it is time to display it, platforms without a
+    /// target frame rate return right away. Returns whether the frame missed
+    /// its vsync.
+    fn wait_for_frame_end(&mut self) -> bool {
+        false
+    }
+
+    /// Displays the back buffer.
+    fn present_frame(&mut self);
+
+    /// The clock frames are timed with.
+    fn clock(&self) -> &dyn Clock;
+
+    fn read_file(&mut self, path: &str) -> std::io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn write_file(
+        &mut self,
+        path: &str,
+        contents: &[u8],
+    ) -> std::io::Result<()> {
+        std::fs::write(path, contents)
+    }
+}
+
+/// Runs frames until `Platform::poll_input` says to stop, recording their
+/// times in `stats` and printing a summary every `stats.window()` frames.
+pub fn run_game_loop<P: Platform>(platform: &mut P, stats: &mut FrameStats) {
+    let mut frame_timer = FrameTimer::new(platform.clock());
+    let mut input = GameInput::default();
+    // Reused for the samples of every frame, it only grows.
+    let mut sound_samples = Vec::new();
+
+    loop {
+        let mut new_input = input.next_frame();
+        if !platform.poll_input(&mut new_input) {
+            break;
+        }
+        input = new_input;
+        let mut video_buffer = platform.back_buffer();
+        let game = platform.game_code();
+        // SAFETY: the pointers come from live references and the game code
+        // was built from this crate.
+        unsafe {
+            (game.update_and_render)(
+                platform.game_memory(),
+                &input,
+                &mut video_buffer,
+            );
+        }
+
+        let sample_count = platform.sound_samples_needed() as usize;
+        sound_samples.clear();
+        sound_samples.resize(sample_count * 2, 0);
+        let mut audio_buffer = GameAudioBuffer::new(
+            platform.samples_per_second(),
+            &mut sound_samples,
+        );
+        // SAFETY: as above.
+        unsafe {
+            (game.get_sound_samples)(platform.game_memory(), &mut audio_buffer);
+        }
+        platform.submit_audio(&sound_samples);
+        let missed_vsync = platform.wait_for_frame_end();
+        platform.present_frame();
+
+        stats.record(frame_timer.frame_end(platform.clock()), missed_vsync);
+        if stats.frames().is_multiple_of(stats.window() as u64) {
+            if let Some(summary) = stats.summary() {
+                println!("{}", summary);
+            }
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! Software rasterizer drawing solid colours into a `GameScreenBuffer`.
+//!
+//! Coordinates are in pixels with the origin at the top left corner, every
+//! primitive is clipped against the buffer so callers can draw partly (or
+//! completely) off screen.
+
+use std::convert::TryFrom;
+
+use crate::GameScreenBuffer;
+
+/// Packs a colour the way the back buffer stores it (`0x00RRGGBB`).
+pub const fn rgb(red: u8, green: u8, blue: u8) -> u32 {
+    (red as u32) << 16 | (green as u32) << 8 | blue as u32
+}
+
+/// Fills the pixels from (`min_x`, `min_y`) up to but not including
+/// (`max_x`, `max_y`).
+pub fn fill_rect(
+    buffer: &mut GameScreenBuffer,
+    min_x: i32,
+    min_y: i32,
+    max_x: i32,
+    max_y: i32,
+    color: u32,
+) {
+    let min_x = min_x.max(0);
+    let min_y = min_y.max(0);
+    let max_x = max_x.min(buffer.width());
+    let max_y = max_y.min(buffer.height());
+    if min_x >= max_x || min_y >= max_y {
+        return;
+    }
+    for y in min_y..max_y {
+        buffer.row_mut(y)[min_x as usize..max_x as usize].fill(color);
+    }
+}
+
+/// Draws the one pixel wide border of the rectangle `fill_rect` would fill.
+pub fn draw_rect(
+    buffer: &mut GameScreenBuffer,
+    min_x: i32,
+    min_y: i32,
+    max_x: i32,
+    max_y: i32,
+    color: u32,
+) {
+    if min_x >= max_x || min_y >= max_y {
+        return;
+    }
+    fill_rect(buffer, min_x, min_y, max_x, min_y + 1, color);
+    fill_rect(buffer, min_x, max_y - 1, max_x, max_y, color);
+    fill_rect(buffer, min_x, min_y, min_x + 1, max_y, color);
+    fill_rect(buffer, max_x - 1, min_y, max_x, max_y, color);
+}
+
+const INSIDE: u8 = 0;
+const LEFT: u8 = 1;
+const RIGHT: u8 = 2;
+const TOP: u8 = 4;
+const BOTTOM: u8 = 8;
+
+fn out_code(buffer: &GameScreenBuffer, x: i64, y: i64) -> u8 {
+    let mut code = INSIDE;
+    if x < 0 {
+        code |= LEFT;
+    } else if x >= buffer.width() as i64 {
+        code |= RIGHT;
+    }
+    if y < 0 {
+        code |= TOP;
+    } else if y >= buffer.height() as i64 {
+        code |= BOTTOM;
+    }
+    code
+}
+
+// The `a` at which the line from (`a0`, `b0`) to (`a1`, `b1`) reaches `b`.
+// The product takes up to 66 bits.
+fn intersect(a0: i64, b0: i64, a1: i64, b1: i64, b: i64) -> i64 {
+    (a0 as i128 + (a1 - a0) as i128 * (b - b0) as i128 / (b1 - b0) as i128)
+        as i64
+}
+
+// Cohen-Sutherland, returns `None` when the segment misses the buffer.
+fn clip_line(
+    buffer: &GameScreenBuffer,
+    (mut x0, mut y0): (i64, i64),
+    (mut x1, mut y1): (i64, i64),
+) -> Option<((i64, i64), (i64, i64))> {
+    let max_x = buffer.width() as i64 - 1;
+    let max_y = buffer.height() as i64 - 1;
+    let mut code0 = out_code(buffer, x0, y0);
+    let mut code1 = out_code(buffer, x1, y1);
+    loop {
+        if code0 | code1 == INSIDE {
+            return Some(((x0, y0), (x1, y1)));
+        }
+        if code0 & code1 != INSIDE {
+            return None;
+        }
+        let code = if code0 != INSIDE { code0 } else { code1 };
+        let (x, y) = if code & BOTTOM != 0 {
+            (intersect(x0, y0, x1, y1, max_y), max_y)
+        } else if code & TOP != 0 {
+            (intersect(x0, y0, x1, y1, 0), 0)
+        } else if code & RIGHT != 0 {
+            (max_x, intersect(y0, x0, y1, x1, max_x))
+        } else {
+            (0, intersect(y0, x0, y1, x1, 0))
+        };
+        if code == code0 {
+            x0 = x;
+            y0 = y;
+            code0 = out_code(buffer, x0, y0);
+        } else {
+            x1 = x;
+            y1 = y;
+            code1 = out_code(buffer, x1, y1);
+        }
+    }
+}
+
+/// Draws the segment between both end points (inclusive) with Bresenham's
+/// algorithm.
+pub fn draw_line(
+    buffer: &mut GameScreenBuffer,
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    color: u32,
+) {
+    let ((x0, y0), (x1, y1)) =
+        match clip_line(buffer, (x0 as i64, y0 as i64), (x1 as i64, y1 as i64))
+        {
+            Some(segment) => segment,
+            None => return,
+        };
+
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let step_x = if x0 < x1 { 1 } else { -1 };
+    let step_y = if y0 < y1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    let (mut x, mut y) = (x0, y0);
+    loop {
+        buffer.put_pixel(x as i32, y as i32, color);
+        if x == x1 && y == y1 {
+            break;
+        }
+        let error2 = 2 * error;
+        if error2 >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if error2 <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+// Whether the pixels from (`min_x`, `min_y`) to (`max_x`, `max_y`)
+// inclusive touch the buffer.
+fn touches(
+    buffer: &GameScreenBuffer,
+    (min_x, min_y): (i64, i64),
+    (max_x, max_y): (i64, i64),
+) -> bool {
+    max_x >= 0
+        && max_y >= 0
+        && min_x < buffer.width() as i64
+        && min_y < buffer.height() as i64
+}
+
+fn put_pixel(buffer: &mut GameScreenBuffer, x: i64, y: i64, color: u32) {
+    if let (Ok(x), Ok(y)) = (i32::try_from(x), i32::try_from(y)) {
+        buffer.put_pixel(x, y, color);
+    }
+}
+
+// The x the midpoint algorithm steps to on row `y` of the octant from
+// (`radius`, 0) to the diagonal: the last one whose midpoint with the next
+// lies inside the circle, the largest with `x * (x - 1) < r² - y²`.
+fn octant_x(radius: i64, y: i64) -> i64 {
+    let limit = radius * radius - y * y;
+    let mut x = ((1.0 + (1.0 + 4.0 * limit as f64).sqrt()) / 2.0) as i64;
+    while x > 0 && x * (x - 1) >= limit {
+        x -= 1;
+    }
+    while (x + 1) * x < limit {
+        x += 1;
+    }
+    x
+}
+
+/// Draws the outline of a circle with the midpoint algorithm.
+pub fn draw_circle(
+    buffer: &mut GameScreenBuffer,
+    center_x: i32,
+    center_y: i32,
+    radius: i32,
+    color: u32,
+) {
+    let (center_x, center_y, radius) =
+        (center_x as i64, center_y as i64, radius as i64);
+    if radius < 0
+        || !touches(
+            buffer,
+            (center_x - radius, center_y - radius),
+            (center_x + radius, center_y + radius),
+        )
+    {
+        return;
+    }
+    // Each octant point is offset by `y` along one axis, only the `y`s
+    // putting it on a row or column of the buffer are stepped to, so huge
+    // circles take no longer than small ones.
+    let (width, height) = (buffer.width() as i64, buffer.height() as i64);
+    let ranges = [
+        (-center_y, height - 1 - center_y),
+        (center_y - height + 1, center_y),
+        (-center_x, width - 1 - center_x),
+        (center_x - width + 1, center_x),
+    ];
+    for &(first, last) in &ranges {
+        for y in first.max(0)..=last.min(radius) {
+            let x = octant_x(radius, y);
+            if x < y {
+                break;
+            }
+            for &(dx, dy) in &[
+                (x, y),
+                (y, x),
+                (-y, x),
+                (-x, y),
+                (-x, -y),
+                (-y, -x),
+                (y, -x),
+                (x, -y),
+            ] {
+                put_pixel(buffer, center_x + dx, center_y + dy, color);
+            }
+        }
+    }
+}
+
+/// Fills every pixel whose centre lies within `radius` of the centre.
+pub fn fill_circle(
+    buffer: &mut GameScreenBuffer,
+    center_x: i32,
+    center_y: i32,
+    radius: i32,
+    color: u32,
+) {
+    let (center_x, center_y, radius) =
+        (center_x as i64, center_y as i64, radius as i64);
+    if radius < 0
+        || !touches(
+            buffer,
+            (center_x - radius, center_y - radius),
+            (center_x + radius, center_y + radius),
+        )
+    {
+        return;
+    }
+    let width = buffer.width() as i64;
+    let radius_squared = radius * radius;
+    // Only the rows within the buffer.
+    let min_dy = (-radius).max(-center_y);
+    let max_dy = radius.min(buffer.height() as i64 - 1 - center_y);
+    for dy in min_dy..=max_dy {
+        let y = (center_y + dy) as i32;
+        let remaining = radius_squared - dy * dy;
+        let half_width = (remaining as f64).sqrt() as i64;
+        fill_rect(
+            buffer,
+            (center_x - half_width).clamp(0, width) as i32,
+            y,
+            (center_x + half_width + 1).clamp(0, width) as i32,
+            y + 1,
+            color,
+        );
+    }
+}
+
+// Twice the signed area of the triangle (`a`, `b`, (`x`, `y`)). The
+// products of coordinate differences take up to 66 bits.
+fn edge(a: (i32, i32), b: (i32, i32), x: i64, y: i64) -> i128 {
+    (b.0 as i128 - a.0 as i128) * (y as i128 - a.1 as i128)
+        - (b.1 as i128 - a.1 as i128) * (x as i128 - a.0 as i128)
+}
+
+fn is_top_left(a: (i32, i32), b: (i32, i32)) -> bool {
+    (a.1 == b.1 && b.0 > a.0) || b.1 < a.1
+}
+
+/// Fills the triangle between three corners.
+///
+/// Pixels on a shared edge belong to exactly one of the triangles sharing
+/// it (top-left fill rule), so meshes don't overdraw.
+pub fn fill_triangle(
+    buffer: &mut GameScreenBuffer,
+    p0: (i32, i32),
+    p1: (i32, i32),
+    p2: (i32, i32),
+    color: u32,
+) {
+    let min_x = p0.0.min(p1.0).min(p2.0);
+    let min_y = p0.1.min(p1.1).min(p2.1);
+    let max_x = p0.0.max(p1.0).max(p2.0);
+    let max_y = p0.1.max(p1.1).max(p2.1);
+    if !touches(
+        buffer,
+        (min_x as i64, min_y as i64),
+        (max_x as i64, max_y as i64),
+    ) {
+        return;
+    }
+    let min_x = min_x.max(0);
+    let min_y = min_y.max(0);
+    let max_x = max_x.min(buffer.width() - 1);
+    let max_y = max_y.min(buffer.height() - 1);
+
+    // Make the winding consistent so inside always means a positive edge
+    // function.
+    let (p1, p2) = if edge(p0, p1, p2.0 as i64, p2.1 as i64) < 0 {
+        (p2, p1)
+    } else {
+        (p1, p2)
+    };
+    if edge(p0, p1, p2.0 as i64, p2.1 as i64) == 0 {
+        return;
+    }
+
+    let bias = |a, b| if is_top_left(a, b) { 0 } else { -1 };
+    let bias0 = bias(p1, p2);
+    let bias1 = bias(p2, p0);
+    let bias2 = bias(p0, p1);
+
+    for y in min_y..=max_y {
+        let row = buffer.row_mut(y);
+        for x in min_x..=max_x {
+            let (px, py) = (x as i64, y as i64);
+            if edge(p1, p2, px, py) + bias0 >= 0
+                && edge(p2, p0, px, py) + bias1 >= 0
+                && edge(p0, p1, px, py) + bias2 >= 0
+            {
+                row[x as usize] = color;
+            }
+        }
+    }
+}
@@ -0,0 +1,490 @@
+//! Recording input and playing it back in a loop.
+//!
+//! When a recording starts the permanent storage of `GameMemory` is copied,
+//! then the input of every frame is kept. Playback restores the copy and
+//! feeds the recorded input back to the game, over and over, so a moment
+//! of gameplay can be watched again while the game code is tweaked and
+//! reloaded. This relies on the game only depending on its memory and its
+//! input.
+
+use crate::{GameInput, GameMemory};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoopState {
+    #[default]
+    Idle,
+    Recording,
+    PlayingBack,
+}
+
+/// A snapshot of game memory and the input recorded after it was taken.
+#[derive(Debug, Default)]
+pub struct InputLoop {
+    state: LoopState,
+    snapshot: Vec<u8>,
+    snapshot_initialized: bool,
+    inputs: Vec<GameInput>,
+    playback_index: usize,
+}
+
+impl InputLoop {
+    pub fn state(&self) -> LoopState {
+        self.state
+    }
+
+    pub fn recorded_frames(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Snapshots `memory` and starts recording, dropping any earlier
+    /// recording.
+    pub fn start_recording(&mut self, memory: &GameMemory) {
+        self.snapshot.clear();
+        self.snapshot.extend_from_slice(memory.permanent_storage());
+        self.snapshot_initialized = memory.is_initialized;
+        self.inputs.clear();
+        self.state = LoopState::Recording;
+    }
+
+    /// Restores the snapshot and plays back the recording from its first
+    /// frame. Does nothing when no frames were recorded.
+    pub fn start_playback(&mut self, memory: &mut GameMemory) {
+        if self.inputs.is_empty() {
+            self.stop();
+            return;
+        }
+        self.restore(memory);
+        self.state = LoopState::PlayingBack;
+    }
+
+    /// Stops recording or playback, the recording is kept.
+    pub fn stop(&mut self) {
+        self.state = LoopState::Idle;
+    }
+
+    /// Cycles from idle to recording to playing back and back to idle, the
+    /// way a single loop key works.
+    pub fn toggle(&mut self, memory: &mut GameMemory) {
+        match self.state() {
+            LoopState::Idle => self.start_recording(memory),
+            LoopState::Recording => self.start_playback(memory),
+            LoopState::PlayingBack => self.stop(),
+        }
+    }
+
+    /// Called once a frame after the platform filled in `input`. Records
+    /// it, or replaces it with the next recorded frame, restoring the
+    /// snapshot whenever playback wraps around.
+    pub fn process(&mut self, input: &mut GameInput, memory: &mut GameMemory) {
+        match self.state() {
+            LoopState::Idle => {}
+            LoopState::Recording => self.inputs.push(*input),
+            LoopState::PlayingBack => {
+                if self.playback_index == self.inputs.len() {
+                    self.restore(memory);
+                }
+                *input = self.inputs[self.playback_index];
+                self.playback_index += 1;
+            }
+        }
+    }
+
+    fn restore(&mut self, memory: &mut GameMemory) {
+        let storage = memory.permanent_storage_mut();
+        let size = storage.len().min(self.snapshot.len());
+        storage[..size].copy_from_slice(&self.snapshot[..size]);
+        memory.is_initialized = self.snapshot_initialized;
+        self.playback_index = 0;
+    }
+}
+
+/// Version of the replay file layout written by `InputLoop::write_to`.
+pub const REPLAY_VERSION: u32 = 2;
+
+const REPLAY_MAGIC: &[u8; 4] = b"HHRP";
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    NotAReplay,
+    Truncated,
+    UnsupportedVersion(u32),
+    /// The replay was recorded by a build whose game state or input is laid
+    /// out differently.
+    BuildMismatch {
+        expected: u64,
+        found: u64,
+    },
+    InvalidSnapshot {
+        size: u64,
+        stored: u64,
+    },
+    /// The snapshot doesn't hash to the checksum stored with it, restoring
+    /// it could leave the game state holding invalid values.
+    SnapshotChecksumMismatch {
+        expected: u64,
+        found: u64,
+    },
+    /// The snapshot is larger than the permanent storage it is meant for.
+    SnapshotTooLarge {
+        size: u64,
+        permanent_storage_size: usize,
+    },
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "{}", error),
+            ReplayError::NotAReplay => write!(f, "missing replay signature"),
+            ReplayError::Truncated => write!(f, "file ends inside the replay"),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "unsupported replay version {}", version)
+            }
+            ReplayError::BuildMismatch { expected, found } => write!(
+                f,
+                "recorded by build {:016x}, this is build {:016x}",
+                found, expected
+            ),
+            ReplayError::InvalidSnapshot { size, stored } => write!(
+                f,
+                "snapshot stores {} bytes of a {} byte memory",
+                stored, size
+            ),
+            ReplayError::SnapshotChecksumMismatch { expected, found } => {
+                write!(
+                    f,
+                    "snapshot hashes to {:016x}, the header says {:016x}",
+                    found, expected
+                )
+            }
+            ReplayError::SnapshotTooLarge {
+                size,
+                permanent_storage_size,
+            } => write!(
+                f,
+                "{} byte snapshot does not fit {} bytes of permanent storage",
+                size, permanent_storage_size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::UnexpectedEof => ReplayError::Truncated,
+            _ => ReplayError::Io(error),
+        }
+    }
+}
+
+/// 64 bit FNV-1a.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Identifies builds that can play each other's replays: the crate
+/// version, the target and the sizes and alignments of the game state and
+/// input.
+pub fn build_hash() -> u64 {
+    let build = format!(
+        "{} {} {} {}:{} {}:{}",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        core::mem::size_of::<crate::GameState>(),
+        core::mem::align_of::<crate::GameState>(),
+        core::mem::size_of::<GameInput>(),
+        core::mem::align_of::<GameInput>(),
+    );
+    fnv1a(build.as_bytes())
+}
+
+/// Hash of everything in the game state, for checking that a replay ends
+/// where it ended when it was recorded. Memory whose state was never
+/// written hashes the same as any other.
+pub fn state_hash(memory: &GameMemory) -> u64 {
+    let mut encoded = Vec::new();
+    memory.initialized_state().encode(&mut encoded);
+    fnv1a(&encoded)
+}
+
+/// Writes a value field by field, little endian, for `state_hash`. Unlike
+/// the bytes of the value it leaves out padding and unlike its `Debug`
+/// output it can't change with formatting.
+pub(crate) trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+macro_rules! encode_le_bytes {
+    ($($ty:ty),*) => {
+        $(impl Encode for $ty {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+        })*
+    };
+}
+
+encode_le_bytes!(u8, u16, u32, u64, i32, f32, f64);
+
+impl Encode for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode(out);
+            }
+        }
+    }
+}
+
+impl<T: Encode> Encode for &T {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self).encode(out);
+    }
+}
+
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        for value in self {
+            value.encode(out);
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+}
+
+impl<A: Encode, B: Encode, C: Encode> Encode for (A, B, C) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+        self.2.encode(out);
+    }
+}
+
+fn write_button(out: &mut Vec<u8>, button: &crate::input::GameButtonState) {
+    out.extend_from_slice(&button.half_transition_count.to_le_bytes());
+    out.push(button.ended_down as u8);
+}
+
+fn write_stick(out: &mut Vec<u8>, stick: &crate::input::GameStickState) {
+    for value in [
+        stick.start_x,
+        stick.start_y,
+        stick.min_x,
+        stick.min_y,
+        stick.max_x,
+        stick.max_y,
+        stick.end_x,
+        stick.end_y,
+    ] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn encode_input(out: &mut Vec<u8>, input: &GameInput) {
+    for controller in input.controllers.iter() {
+        out.push(controller.is_connected as u8);
+        out.push(controller.is_analog as u8);
+        write_stick(out, &controller.left_stick);
+        write_stick(out, &controller.right_stick);
+        for button in controller.buttons() {
+            write_button(out, button);
+        }
+    }
+}
+
+fn read_bytes<const N: usize, R: std::io::Read>(
+    reader: &mut R,
+) -> Result<[u8; N], ReplayError> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u32<R: std::io::Read>(reader: &mut R) -> Result<u32, ReplayError> {
+    Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_u64<R: std::io::Read>(reader: &mut R) -> Result<u64, ReplayError> {
+    Ok(u64::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_f32<R: std::io::Read>(reader: &mut R) -> Result<f32, ReplayError> {
+    Ok(f32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_bool<R: std::io::Read>(reader: &mut R) -> Result<bool, ReplayError> {
+    Ok(read_bytes::<1, R>(reader)?[0] != 0)
+}
+
+fn decode_input<R: std::io::Read>(
+    reader: &mut R,
+) -> Result<GameInput, ReplayError> {
+    let mut input = GameInput::default();
+    for controller in input.controllers.iter_mut() {
+        controller.is_connected = read_bool(reader)?;
+        controller.is_analog = read_bool(reader)?;
+        for stick in [&mut controller.left_stick, &mut controller.right_stick] {
+            for value in [
+                &mut stick.start_x,
+                &mut stick.start_y,
+                &mut stick.min_x,
+                &mut stick.min_y,
+                &mut stick.max_x,
+                &mut stick.max_y,
+                &mut stick.end_x,
+                &mut stick.end_y,
+            ] {
+                *value = read_f32(reader)?;
+            }
+        }
+        for button in controller.buttons_mut() {
+            button.half_transition_count = read_u32(reader)?;
+            button.ended_down = read_bool(reader)?;
+        }
+    }
+    Ok(input)
+}
+
+impl InputLoop {
+    /// Writes the snapshot and the recorded input as a replay file.
+    ///
+    /// All values are little endian:
+    ///
+    /// | field                  | type          |
+    /// |------------------------|---------------|
+    /// | magic `HHRP`           | 4 bytes       |
+    /// | version                | u32           |
+    /// | build hash             | u64           |
+    /// | memory snapshot size   | u64           |
+    /// | stored snapshot bytes  | u64           |
+    /// | snapshot checksum      | u64           |
+    /// | frame count            | u32           |
+    /// | memory initialized     | u8            |
+    /// | snapshot               | stored bytes  |
+    /// | input records          | per frame     |
+    ///
+    /// Trailing zeros of the snapshot are not stored, the checksum is the
+    /// `fnv1a` of the whole snapshot including them. An input record holds
+    /// every controller in order: connected and analog flags as bytes, both
+    /// sticks as eight f32 each, then the half transition count (u32) and
+    /// ended down flag (u8) of every button in `buttons()` order.
+    pub fn write_to<W: std::io::Write>(
+        &self,
+        mut writer: W,
+    ) -> std::io::Result<()> {
+        let stored = self
+            .snapshot
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |last| last + 1);
+        let mut header = Vec::with_capacity(49);
+        header.extend_from_slice(REPLAY_MAGIC);
+        header.extend_from_slice(&REPLAY_VERSION.to_le_bytes());
+        header.extend_from_slice(&build_hash().to_le_bytes());
+        header.extend_from_slice(&(self.snapshot.len() as u64).to_le_bytes());
+        header.extend_from_slice(&(stored as u64).to_le_bytes());
+        header.extend_from_slice(&fnv1a(&self.snapshot).to_le_bytes());
+        header.extend_from_slice(&(self.inputs.len() as u32).to_le_bytes());
+        header.push(self.snapshot_initialized as u8);
+        writer.write_all(&header)?;
+        writer.write_all(&self.snapshot[..stored])?;
+
+        let mut record = Vec::new();
+        for input in self.inputs.iter() {
+            record.clear();
+            encode_input(&mut record, input);
+            writer.write_all(&record)?;
+        }
+        writer.flush()
+    }
+
+    /// Reads a replay written by `write_to`, ready for `start_playback` on
+    /// `memory`.
+    pub fn read_from<R: std::io::Read>(
+        mut reader: R,
+        memory: &GameMemory,
+    ) -> Result<Self, ReplayError> {
+        use std::io::Read;
+
+        if &read_bytes::<4, R>(&mut reader)? != REPLAY_MAGIC {
+            return Err(ReplayError::NotAReplay);
+        }
+        let version = read_u32(&mut reader)?;
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let found = read_u64(&mut reader)?;
+        if found != build_hash() {
+            return Err(ReplayError::BuildMismatch {
+                expected: build_hash(),
+                found,
+            });
+        }
+        let size = read_u64(&mut reader)?;
+        let stored = read_u64(&mut reader)?;
+        let checksum = read_u64(&mut reader)?;
+        let frame_count = read_u32(&mut reader)?;
+        let snapshot_initialized = read_bool(&mut reader)?;
+        if stored > size || size > isize::MAX as u64 {
+            return Err(ReplayError::InvalidSnapshot { size, stored });
+        }
+        if size > memory.permanent_storage_size() as u64 {
+            return Err(ReplayError::SnapshotTooLarge {
+                size,
+                permanent_storage_size: memory.permanent_storage_size(),
+            });
+        }
+
+        let mut snapshot = Vec::new();
+        reader.by_ref().take(stored).read_to_end(&mut snapshot)?;
+        if snapshot.len() as u64 != stored {
+            return Err(ReplayError::Truncated);
+        }
+        snapshot.resize(size as usize, 0);
+        // The snapshot is restored over the game state byte for byte, only
+        // the bytes that were recorded make a valid one.
+        let found = fnv1a(&snapshot);
+        if found != checksum {
+            return Err(ReplayError::SnapshotChecksumMismatch {
+                expected: checksum,
+                found,
+            });
+        }
+        let inputs = (0..frame_count)
+            .map(|_| decode_input(&mut reader))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            state: LoopState::Idle,
+            snapshot,
+            snapshot_initialized,
+            inputs,
+            playback_index: 0,
+        })
+    }
+}
@@ -5,6 +5,7 @@ pub struct OffScreenBuffer {
     info: win32::BITMAPINFO,
     pub width: i32,
     pub height: i32,
+    pub pitch: usize,
     pub bytes_per_pixel: usize,
 }
 
@@ -21,6 +22,7 @@ impl OffScreenBuffer {
             info: win32::BITMAPINFO::default(),
             width: 0,
             height: 0,
+            pitch: 0,
             bytes_per_pixel: core::mem::size_of::<u32>(),
         }
     }
@@ -110,7 +112,8 @@ impl OffScreenBuffer {
         self.info.bmiHeader.biBitCount = 32;
         self.info.bmiHeader.biCompression = win32::BI_RGB;
 
-        let size = self.bytes_per_pixel * (self.width * self.height) as usize;
+        self.pitch = self.bytes_per_pixel * self.width as usize;
+        let size = self.pitch * self.height as usize;
         self.memory = unsafe {
             win32::VirtualAlloc(
                 core::ptr::null_mut::<win32::c_void>(),
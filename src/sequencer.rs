@@ -0,0 +1,461 @@
+//! Tracker style music.
+//!
+//! A `Song` is a list of `Pattern`s played in `order`. Each pattern is a grid
+//! of rows by channels, and each cell can start a note on its channel,
+//! release it, or leave it alone. A new note on a channel releases the one
+//! before it. The tempo is given in beats per minute and `rows_per_beat`.
+//!
+//! `Song::store` copies a song into game memory, where the `Sequencer`
+//! playing it finds it through `Assets`, so the sequencer is plain data
+//! that can live in the game state.
+//!
+//! `Sequencer::render` is called with the number of frames about to be
+//! mixed. It schedules the notes of the rows starting within them as mixer
+//! note events, on the frame each row starts, and moves on by that many
+//! frames. Music therefore stays in time with the samples mixed, however the
+//! platform asks for them.
+
+use core::ops::Range;
+
+use crate::envelope::Adsr;
+use crate::mixer::{Mixer, SoundId, Voice, VoiceId};
+use crate::oscillator::Waveform;
+use crate::replay::Encode;
+use crate::{ArenaError, AssetSlice, Assets, GameMemory};
+
+/// Channels a song's patterns can have.
+pub const MAX_CHANNELS: usize = 32;
+
+/// Index of an instrument in `Song::instruments`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstrumentId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instrument {
+    Synth {
+        waveform: Waveform,
+        adsr: Adsr,
+        volume: f32,
+    },
+    /// A loaded sound, played faster or slower to reach each note from
+    /// `root_frequency`, the pitch it was recorded at.
+    Sample {
+        sound: SoundId,
+        root_frequency: f32,
+        adsr: Adsr,
+        volume: f32,
+    },
+}
+
+impl Encode for Instrument {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Instrument::Synth {
+                waveform,
+                adsr,
+                volume,
+            } => {
+                out.push(0);
+                waveform.encode(out);
+                adsr.encode(out);
+                volume.encode(out);
+            }
+            Instrument::Sample {
+                sound,
+                root_frequency,
+                adsr,
+                volume,
+            } => {
+                out.push(1);
+                sound.encode(out);
+                root_frequency.encode(out);
+                adsr.encode(out);
+                volume.encode(out);
+            }
+        }
+    }
+}
+
+impl Instrument {
+    pub(crate) fn voice(&self, frequency: f32) -> Voice {
+        match *self {
+            Instrument::Synth {
+                waveform,
+                adsr,
+                volume,
+            } => Voice::oscillator(waveform, frequency, volume, 0.0)
+                .with_envelope(adsr),
+            Instrument::Sample {
+                sound,
+                root_frequency,
+                adsr,
+                volume,
+            } => {
+                let mut voice =
+                    Voice::sound(sound, volume, 0.0).with_envelope(adsr);
+                voice.pitch = frequency / root_frequency;
+                voice
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cell {
+    Empty,
+    /// Starts MIDI note `key`, 69 is A440.
+    Note {
+        key: u8,
+        instrument: InstrumentId,
+        velocity: f32,
+    },
+    /// Releases the channel's note.
+    Off,
+}
+
+/// Frequency in Hz of MIDI note `key` in equal temperament.
+pub fn note_frequency(key: u8) -> f32 {
+    440.0 * 2f32.powf((key as f32 - 69.0) / 12.0)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    rows: usize,
+    channels: usize,
+    // Row after row.
+    cells: Vec<Cell>,
+}
+
+impl Pattern {
+    pub fn new(rows: usize, channels: usize) -> Self {
+        assert!(rows > 0, "a pattern needs at least one row");
+        Self {
+            rows,
+            channels,
+            cells: vec![Cell::Empty; rows * channels],
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn cell(&self, row: usize, channel: usize) -> Cell {
+        assert!(row < self.rows && channel < self.channels);
+        self.cells[row * self.channels + channel]
+    }
+
+    pub fn set(&mut self, row: usize, channel: usize, cell: Cell) {
+        assert!(row < self.rows && channel < self.channels);
+        self.cells[row * self.channels + channel] = cell;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Song {
+    pub bpm: f32,
+    pub rows_per_beat: u32,
+    pub instruments: Vec<Instrument>,
+    pub patterns: Vec<Pattern>,
+    /// Indices into `patterns` in the order they play.
+    pub order: Vec<usize>,
+    /// Positions in `order` played over and over once playback reaches the
+    /// end of the range, the song stops at the end of `order` without one.
+    pub loop_range: Option<Range<usize>>,
+}
+
+impl Song {
+    /// Copies the song into the assets of `memory`.
+    ///
+    /// # Panics
+    ///
+    /// If the tempo is not positive, the order or loop range refer to
+    /// missing patterns or a pattern has more than `MAX_CHANNELS`.
+    pub fn store(
+        &self,
+        memory: &mut GameMemory,
+    ) -> Result<StoredSong, ArenaError> {
+        assert!(
+            self.bpm > 0.0 && self.rows_per_beat > 0,
+            "the tempo must be positive"
+        );
+        assert!(
+            self.order
+                .iter()
+                .all(|&pattern| pattern < self.patterns.len()),
+            "the order refers to a missing pattern"
+        );
+        if let Some(range) = &self.loop_range {
+            assert!(
+                range.start < range.end && range.end <= self.order.len(),
+                "the loop range must be a part of the order"
+            );
+        }
+        assert!(
+            self.patterns
+                .iter()
+                .all(|pattern| pattern.channels <= MAX_CHANNELS),
+            "patterns can have at most {} channels",
+            MAX_CHANNELS
+        );
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                Ok(StoredPattern {
+                    rows: pattern.rows,
+                    channels: pattern.channels,
+                    cells: memory.push_asset(&pattern.cells)?,
+                })
+            })
+            .collect::<Result<Vec<_>, ArenaError>>()?;
+        Ok(StoredSong {
+            bpm: self.bpm,
+            rows_per_beat: self.rows_per_beat,
+            instruments: memory.push_asset(&self.instruments)?,
+            patterns: memory.push_asset(&patterns)?,
+            order: memory.push_asset(&self.order)?,
+            loop_range: self
+                .loop_range
+                .as_ref()
+                .map(|range| (range.start, range.end)),
+        })
+    }
+}
+
+/// A song copied into game memory by `Song::store`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StoredSong {
+    pub bpm: f32,
+    pub rows_per_beat: u32,
+    instruments: AssetSlice<Instrument>,
+    patterns: AssetSlice<StoredPattern>,
+    order: AssetSlice<usize>,
+    loop_range: Option<(usize, usize)>,
+}
+
+impl Encode for StoredSong {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.bpm.encode(out);
+        self.rows_per_beat.encode(out);
+        self.instruments.encode(out);
+        self.patterns.encode(out);
+        self.order.encode(out);
+        self.loop_range.encode(out);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct StoredPattern {
+    rows: usize,
+    channels: usize,
+    cells: AssetSlice<Cell>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sequencer {
+    song: StoredSong,
+    playing: bool,
+    // The next row to play.
+    order_position: usize,
+    row: usize,
+    // Frames rendered and rows played so far.
+    frame: u64,
+    rows: u64,
+    // Row `origin_row` starts on `origin_frame`, the ones after it follow at
+    // the current tempo. Moved forward when the tempo changes.
+    origin_row: u64,
+    origin_frame: u64,
+    samples_per_second: u32,
+    // The note each channel plays.
+    channels: [Option<VoiceId>; MAX_CHANNELS],
+}
+
+impl Encode for Sequencer {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.song.encode(out);
+        self.playing.encode(out);
+        self.order_position.encode(out);
+        self.row.encode(out);
+        self.frame.encode(out);
+        self.rows.encode(out);
+        self.origin_row.encode(out);
+        self.origin_frame.encode(out);
+        self.samples_per_second.encode(out);
+        self.channels.encode(out);
+    }
+}
+
+impl Sequencer {
+    pub fn new(song: StoredSong) -> Self {
+        Self {
+            playing: !song.order.is_empty(),
+            song,
+            order_position: 0,
+            row: 0,
+            frame: 0,
+            rows: 0,
+            origin_row: 0,
+            origin_frame: 0,
+            samples_per_second: 0,
+            channels: [None; MAX_CHANNELS],
+        }
+    }
+
+    pub fn song(&self) -> &StoredSong {
+        &self.song
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// The position in the order and the row within its pattern of the next
+    /// row to play.
+    pub fn position(&self) -> (usize, usize) {
+        (self.order_position, self.row)
+    }
+
+    /// Frames rendered so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Changes the tempo from the next row on.
+    pub fn set_bpm(&mut self, bpm: f32) {
+        assert!(bpm > 0.0, "the tempo must be positive");
+        self.rebase();
+        self.song.bpm = bpm;
+    }
+
+    /// Releases every note and stops.
+    pub fn stop(&mut self, mixer: &mut Mixer) {
+        for voice in self.channels.iter_mut().filter_map(Option::take) {
+            release(mixer, voice, 0);
+        }
+        self.playing = false;
+    }
+
+    /// Schedules the rows starting within the next `frame_count` frames
+    /// mixed at `samples_per_second` and moves past them. `assets` are those
+    /// of the memory the song was stored in.
+    pub fn render(
+        &mut self,
+        assets: Assets,
+        mixer: &mut Mixer,
+        frame_count: usize,
+        samples_per_second: u32,
+    ) {
+        // Every row would start on the same frame, and a looping song would
+        // never get past it.
+        if samples_per_second == 0 {
+            return;
+        }
+        if samples_per_second != self.samples_per_second {
+            self.rebase();
+            self.samples_per_second = samples_per_second;
+        }
+        let end = self.frame + frame_count as u64;
+        while self.playing {
+            let start = self.row_frame(self.rows);
+            if start >= end {
+                break;
+            }
+            let offset = start.saturating_sub(self.frame) as u32;
+            self.play_row(assets, mixer, offset);
+        }
+        self.frame = end;
+    }
+
+    fn frames_per_row(&self) -> f64 {
+        self.samples_per_second as f64 * 60.0
+            / (self.song.bpm as f64 * self.song.rows_per_beat as f64)
+    }
+
+    // Rounded separately for every row so the rows don't drift.
+    fn row_frame(&self, row: u64) -> u64 {
+        let rows = (row - self.origin_row) as f64;
+        self.origin_frame + (rows * self.frames_per_row()).round() as u64
+    }
+
+    fn rebase(&mut self) {
+        self.origin_frame = self.row_frame(self.rows);
+        self.origin_row = self.rows;
+    }
+
+    fn play_row(&mut self, assets: Assets, mixer: &mut Mixer, offset: u32) {
+        let order = assets.get(self.song.order);
+        let pattern =
+            assets.get(self.song.patterns)[order[self.order_position]];
+        let cells = assets.get(pattern.cells);
+        for channel in 0..pattern.channels {
+            let cell = cells[self.row * pattern.channels + channel];
+            if cell == Cell::Empty {
+                continue;
+            }
+            if let Some(voice) = self.channels[channel].take() {
+                release(mixer, voice, offset);
+            }
+            if let Cell::Note {
+                key,
+                instrument,
+                velocity,
+            } = cell
+            {
+                let instrument = match assets
+                    .get(self.song.instruments)
+                    .get(instrument.0 as usize)
+                {
+                    Some(instrument) => instrument,
+                    None => continue,
+                };
+                let frequency = note_frequency(key);
+                let voice = mixer.play(instrument.voice(frequency));
+                if let Some(voice) = voice {
+                    if !mixer.note_on(voice, offset, frequency, velocity) {
+                        // The note queue is full, the voice would never
+                        // sound.
+                        mixer.stop(voice);
+                        continue;
+                    }
+                }
+                self.channels[channel] = voice;
+            }
+        }
+
+        self.rows += 1;
+        self.row += 1;
+        if self.row < pattern.rows {
+            return;
+        }
+        self.row = 0;
+        self.order_position += 1;
+        match self.song.loop_range {
+            Some((start, end)) if self.order_position >= end => {
+                self.order_position = start;
+            }
+            _ if self.order_position >= order.len() => {
+                // The last row plays out before its notes are released.
+                let end = self.row_frame(self.rows);
+                let offset = end.saturating_sub(self.frame) as u32;
+                for voice in self.channels.iter_mut().filter_map(Option::take) {
+                    release(mixer, voice, offset);
+                }
+                self.playing = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+// Releases the note of `voice` `offset` frames into the next mix, or cuts it
+// off when the note queue is full so it doesn't sustain forever.
+pub(crate) fn release(mixer: &mut Mixer, voice: VoiceId, offset: u32) {
+    if !mixer.note_off(voice, offset) {
+        mixer.stop(voice);
+    }
+}
@@ -9,54 +9,29 @@ type DirectSoundCreate = fn(
 type SomeFunction = win32::FARPROC;
 
 pub struct SoundOutput {
-    pub play_cursor: u32,
     pub latency_sample_count: u32,
-    pub write_cursor: u32,
     pub samples_per_second: u32,
-    pub running_sample_index: u32,
     pub buffer_size: u32,
     pub bytes_per_sample: u32,
-    pub time: f32,
-    pub volume: i16,
-    pub wave_period: u32,
     pub buffer: win32::LPDIRECTSOUNDBUFFER,
-    pub freq: u32,
 }
 
 impl Default for SoundOutput {
     fn default() -> Self {
-        SoundOutput::new(
-            48000,
-            (core::mem::size_of::<u16>() * 2) as u32,
-            251,
-            1500,
-        )
+        SoundOutput::new(48000, (core::mem::size_of::<u16>() * 2) as u32)
     }
 }
 
 impl SoundOutput {
-    pub fn new(
-        samples_per_second: u32,
-        bytes_per_sample: u32,
-        freq: u32,
-        volume: i16,
-    ) -> Self {
+    pub fn new(samples_per_second: u32, bytes_per_sample: u32) -> Self {
         let buffer_size = samples_per_second * bytes_per_sample;
-        let wave_period = samples_per_second.div_euclid(freq);
         let latency_sample_count = samples_per_second / 20;
         Self {
-            time: 0.0,
             latency_sample_count,
             samples_per_second,
             buffer_size,
             bytes_per_sample,
-            volume,
-            wave_period,
-            running_sample_index: 0,
             buffer: core::ptr::null_mut(),
-            play_cursor: 0,
-            write_cursor: 0,
-            freq,
         }
     }
     pub fn init_sound(
@@ -203,11 +178,12 @@ impl SoundOutput {
         } else {
         }
     }
+    /// Copies `samples` into the part of the buffer `write` covers, the
+    /// lock splits it where it wraps around the end.
     pub fn fill_sound_buffer(
         &mut self,
-        game_audio: &handmade_hero::GameAudioBuffer,
-        lock_offset: u32,
-        bytes_to_lock: u32,
+        write: &handmade_hero::audio_ring::AudioWrite,
+        samples: &[i16],
     ) {
         let buffer = unsafe { self.buffer.as_mut().unwrap() };
 
@@ -216,11 +192,10 @@ impl SoundOutput {
         let mut region2: win32::LPVOID = core::ptr::null_mut();
         let mut region2_size = 0u32;
 
-        let mut game_audio_buffer = game_audio.samples;
         if win32::SUCCEEDED(unsafe {
             buffer.Lock(
-                lock_offset,
-                bytes_to_lock,
+                write.lock_offset,
+                write.bytes_to_write,
                 &mut region1,
                 &mut region1_size,
                 &mut region2,
@@ -228,32 +203,25 @@ impl SoundOutput {
                 0,
             )
         }) {
-            let mut sample_out: *mut i16 = region1.cast();
-            for _ in 0..region1_size.div_euclid(self.bytes_per_sample) {
-                unsafe {
-                    sample_out.write(*game_audio_buffer);
-                    sample_out = sample_out.add(1);
-                    game_audio_buffer = game_audio_buffer.add(1);
-                    sample_out.write(*game_audio_buffer);
-                    sample_out = sample_out.add(1);
-                    game_audio_buffer = game_audio_buffer.add(1);
-                }
-                self.running_sample_index += 1;
-            }
-            sample_out = region2.cast();
-            for _ in 0..region2_size.div_euclid(self.bytes_per_sample) {
-                unsafe {
-                    sample_out.write(*game_audio_buffer);
-                    sample_out = sample_out.add(1);
-                    game_audio_buffer = game_audio_buffer.add(1);
-                    sample_out.write(*game_audio_buffer);
-                    sample_out = sample_out.add(1);
-                    game_audio_buffer = game_audio_buffer.add(1);
-                }
-                self.running_sample_index += 1;
-            }
-
+            let sample_size = core::mem::size_of::<i16>();
+            let region1_len = region1_size as usize / sample_size;
+            let region2_len = region2_size as usize / sample_size;
+            let (first, rest) =
+                samples.split_at(region1_len.min(samples.len()));
+            let second = &rest[..region2_len.min(rest.len())];
+            // SAFETY: Lock handed out `region1_size` and `region2_size`
+            // writable bytes, at least as many as the slices copied.
             unsafe {
+                core::ptr::copy_nonoverlapping(
+                    first.as_ptr(),
+                    region1.cast::<i16>(),
+                    first.len(),
+                );
+                core::ptr::copy_nonoverlapping(
+                    second.as_ptr(),
+                    region2.cast::<i16>(),
+                    second.len(),
+                );
                 buffer.Unlock(region1, region1_size, region2, region2_size);
             }
         } else {
@@ -0,0 +1,290 @@
+//! Clocks, frame timing and frame rate locking.
+//!
+//! Everything that measures time goes through `Clock`, so the frame pacer
+//! and the frame statistics run the same on every platform and against a
+//! `FakeClock` in tests.
+
+use core::cell::Cell;
+
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::_rdtsc;
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::_rdtsc;
+
+/// The processor's time stamp counter, zero where there is none.
+pub fn read_cycle_counter() -> u64 {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    // SAFETY: rdtsc has no preconditions on x86.
+    unsafe {
+        _rdtsc()
+    }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    0
+}
+
+/// A monotonic tick counter plus the processor's cycle counter.
+pub trait Clock {
+    /// Current value of the counter.
+    fn now(&self) -> u64;
+
+    /// Ticks per second of `now`.
+    fn ticks_per_second(&self) -> u64;
+
+    /// Current value of the processor's cycle counter.
+    fn cycles(&self) -> u64 {
+        read_cycle_counter()
+    }
+
+    fn seconds_elapsed(&self, start: u64, end: u64) -> f32 {
+        end.saturating_sub(start) as f32 / self.ticks_per_second() as f32
+    }
+
+    /// Blocks for about `seconds`, possibly longer.
+    fn sleep(&mut self, seconds: f32) {
+        std::thread::sleep(std::time::Duration::from_secs_f32(seconds));
+    }
+}
+
+/// `std::time::Instant` in nanoseconds since the clock was made.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: std::time::Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_nanos() as u64
+    }
+
+    fn ticks_per_second(&self) -> u64 {
+        1_000_000_000
+    }
+}
+
+/// `clock_gettime(CLOCK_MONOTONIC)` in nanoseconds.
+#[cfg(unix)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MonotonicClock;
+
+#[cfg(unix)]
+impl Clock for MonotonicClock {
+    fn now(&self) -> u64 {
+        let mut time = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `time` is a valid timespec to write to, CLOCK_MONOTONIC is
+        // always available.
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+        time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64
+    }
+
+    fn ticks_per_second(&self) -> u64 {
+        1_000_000_000
+    }
+}
+
+/// A clock that only moves when told to, for testing code that waits.
+///
+/// Every read advances it by `ticks_per_read` so spin loops finish, and
+/// `sleep` advances it by the requested time plus `oversleep`. The cycle
+/// counter runs at `cycles_per_tick` of the tick counter.
+#[derive(Debug, Default)]
+pub struct FakeClock {
+    ticks: Cell<u64>,
+    pub ticks_per_second: u64,
+    pub ticks_per_read: u64,
+    pub cycles_per_tick: u64,
+    /// Extra seconds every sleep takes, like a coarse scheduler.
+    pub oversleep: f32,
+    /// Total seconds asked for by `sleep`.
+    pub slept: f32,
+    reads: Cell<u64>,
+}
+
+impl FakeClock {
+    pub fn new(ticks_per_second: u64) -> Self {
+        Self {
+            ticks_per_second,
+            ticks_per_read: 1,
+            ..Self::default()
+        }
+    }
+
+    pub fn advance(&self, seconds: f32) {
+        let ticks = (seconds * self.ticks_per_second as f32).round() as u64;
+        self.ticks.set(self.ticks.get() + ticks);
+    }
+
+    /// How often `now` was called.
+    pub fn reads(&self) -> u64 {
+        self.reads.get()
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> u64 {
+        self.reads.set(self.reads.get() + 1);
+        let now = self.ticks.get();
+        self.ticks.set(now + self.ticks_per_read);
+        now
+    }
+
+    fn ticks_per_second(&self) -> u64 {
+        self.ticks_per_second
+    }
+
+    fn cycles(&self) -> u64 {
+        self.ticks.get() * self.cycles_per_tick
+    }
+
+    fn sleep(&mut self, seconds: f32) {
+        self.slept += seconds;
+        self.advance(seconds + self.oversleep);
+    }
+}
+
+/// Time and cycles one frame took.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameTime {
+    pub seconds: f32,
+    pub cycles: u64,
+}
+
+impl FrameTime {
+    pub fn ms_per_frame(&self) -> f32 {
+        1000.0 * self.seconds
+    }
+
+    pub fn frames_per_second(&self) -> f32 {
+        if self.seconds > 0.0 {
+            1.0 / self.seconds
+        } else {
+            0.0
+        }
+    }
+
+    pub fn mega_cycles_per_frame(&self) -> f32 {
+        self.cycles as f32 / (1000.0 * 1000.0)
+    }
+}
+
+/// Measures the time between consecutive frame ends.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTimer {
+    last_counter: u64,
+    last_cycles: u64,
+}
+
+impl FrameTimer {
+    /// Starts timing the first frame now.
+    pub fn new<C: Clock + ?Sized>(clock: &C) -> Self {
+        Self {
+            last_counter: clock.now(),
+            last_cycles: clock.cycles(),
+        }
+    }
+
+    /// Ends the current frame and starts the next.
+    pub fn frame_end<C: Clock + ?Sized>(&mut self, clock: &C) -> FrameTime {
+        let counter = clock.now();
+        let cycles = clock.cycles();
+        let frame = FrameTime {
+            seconds: clock.seconds_elapsed(self.last_counter, counter),
+            cycles: cycles.wrapping_sub(self.last_cycles),
+        };
+        self.last_counter = counter;
+        self.last_cycles = cycles;
+        frame
+    }
+}
+
+/// How a frame went, as measured by `FramePacer::wait_for_frame_end`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameWait {
+    /// Seconds from the end of the last frame until the wait started.
+    pub work_seconds: f32,
+    /// Seconds from the end of the last frame until this one ended.
+    pub frame_seconds: f32,
+    /// The work alone took longer than a frame.
+    pub missed: bool,
+}
+
+/// Holds frames to a fixed rate.
+///
+/// Whatever time is left of a frame is slept away except for the last
+/// `sleep_margin` seconds, which are spun on the clock since the OS may
+/// wake a sleeping thread late.
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    target_seconds_per_frame: f32,
+    pub sleep_margin: f32,
+    last_frame_end: u64,
+    missed_frames: u32,
+}
+
+impl FramePacer {
+    /// Paces frames at `frames_per_second`, the first frame starts now.
+    pub fn new<C: Clock + ?Sized>(frames_per_second: f32, clock: &C) -> Self {
+        Self {
+            target_seconds_per_frame: 1.0 / frames_per_second,
+            sleep_margin: 0.002,
+            last_frame_end: clock.now(),
+            missed_frames: 0,
+        }
+    }
+
+    pub fn target_seconds_per_frame(&self) -> f32 {
+        self.target_seconds_per_frame
+    }
+
+    /// Frames whose work took longer than the target.
+    pub fn missed_frames(&self) -> u32 {
+        self.missed_frames
+    }
+
+    /// Waits until the current frame has taken the target time, or returns
+    /// right away when it already took longer.
+    pub fn wait_for_frame_end<C: Clock + ?Sized>(
+        &mut self,
+        clock: &mut C,
+    ) -> FrameWait {
+        let work_end = clock.now();
+        let work_seconds = clock.seconds_elapsed(self.last_frame_end, work_end);
+        let missed = work_seconds > self.target_seconds_per_frame;
+        let mut frame_end = work_end;
+        if missed {
+            self.missed_frames += 1;
+        } else {
+            let sleep_seconds = self.target_seconds_per_frame
+                - work_seconds
+                - self.sleep_margin;
+            if sleep_seconds > 0.0 {
+                clock.sleep(sleep_seconds);
+            }
+            while clock.seconds_elapsed(self.last_frame_end, frame_end)
+                < self.target_seconds_per_frame
+            {
+                core::hint::spin_loop();
+                frame_end = clock.now();
+            }
+        }
+        let frame_seconds =
+            clock.seconds_elapsed(self.last_frame_end, frame_end);
+        self.last_frame_end = frame_end;
+        FrameWait {
+            work_seconds,
+            frame_seconds,
+            missed,
+        }
+    }
+}
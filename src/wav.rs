@@ -0,0 +1,253 @@
+//! RIFF/WAVE PCM loading and writing.
+
+use std::borrow::Cow;
+
+use crate::bytes::{read_u16_le, read_u32_le, Truncated};
+use crate::replay::Encode;
+use crate::{ArenaError, AssetSlice, Assets, GameMemory};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// A decoded sound, samples are interleaved per channel and converted to
+/// signed 16 bit. Sounds loaded from game memory borrow their samples.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadedSound<'a> {
+    pub samples_per_second: u32,
+    pub channels: u16,
+    pub samples: Cow<'a, [i16]>,
+}
+
+/// A sound copied into game memory by `LoadedSound::store`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StoredSound {
+    pub samples_per_second: u32,
+    pub channels: u16,
+    pub samples: AssetSlice<i16>,
+}
+
+impl Encode for StoredSound {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.samples_per_second.encode(out);
+        self.channels.encode(out);
+        self.samples.encode(out);
+    }
+}
+
+impl StoredSound {
+    /// The sound, borrowing its samples from `assets`.
+    pub fn load<'a>(&self, assets: Assets<'a>) -> LoadedSound<'a> {
+        LoadedSound {
+            samples_per_second: self.samples_per_second,
+            channels: self.channels,
+            samples: Cow::Borrowed(assets.get(self.samples)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WavError {
+    NotAWave,
+    Truncated,
+    MissingChunk(&'static str),
+    UnsupportedFormat(u16),
+    UnsupportedChannels(u16),
+    UnsupportedBitsPerSample(u16),
+    InvalidSampleRate(u32),
+}
+
+impl std::fmt::Display for WavError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WavError::NotAWave => write!(f, "missing RIFF/WAVE signature"),
+            WavError::Truncated => write!(f, "file ends inside a chunk"),
+            WavError::MissingChunk(id) => write!(f, "no {:?} chunk", id),
+            WavError::UnsupportedFormat(format) => {
+                write!(f, "unsupported format tag {:#x}", format)
+            }
+            WavError::UnsupportedChannels(channels) => {
+                write!(f, "unsupported channel count {}", channels)
+            }
+            WavError::UnsupportedBitsPerSample(bits) => {
+                write!(f, "unsupported {} bits per sample", bits)
+            }
+            WavError::InvalidSampleRate(rate) => {
+                write!(f, "invalid sample rate {}", rate)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WavError {}
+
+impl From<Truncated> for WavError {
+    fn from(_: Truncated) -> Self {
+        WavError::Truncated
+    }
+}
+
+impl LoadedSound<'static> {
+    /// Decodes an 8 or 16 bit, mono or stereo PCM WAVE file.
+    pub fn from_wav(data: &[u8]) -> Result<Self, WavError> {
+        if data.get(0..4) != Some(b"RIFF") || data.get(8..12) != Some(b"WAVE") {
+            return Err(WavError::NotAWave);
+        }
+
+        let mut format = None;
+        let mut sample_data = None;
+        let mut offset = 12;
+        while offset + 8 <= data.len() {
+            let id = &data[offset..offset + 4];
+            let size = read_u32_le(data, offset + 4)? as usize;
+            let body = offset + 8;
+            let chunk = data.get(body..body + size);
+            match id {
+                b"fmt " => format = Some(chunk.ok_or(WavError::Truncated)?),
+                // Writers that stream audio often leave the size of the last
+                // chunk unpatched, take whatever is there.
+                b"data" => sample_data = Some(chunk.unwrap_or(&data[body..])),
+                _ => {}
+            }
+            // Chunks are padded to an even size.
+            offset = body + size + (size & 1);
+        }
+
+        let format = format.ok_or(WavError::MissingChunk("fmt "))?;
+        let sample_data = sample_data.ok_or(WavError::MissingChunk("data"))?;
+
+        let mut format_tag = read_u16_le(format, 0)?;
+        let channels = read_u16_le(format, 2)?;
+        let samples_per_second = read_u32_le(format, 4)?;
+        let bits_per_sample = read_u16_le(format, 14)?;
+        if format_tag == WAVE_FORMAT_EXTENSIBLE {
+            // The first two bytes of the sub format GUID hold the real tag.
+            format_tag = read_u16_le(format, 24)?;
+        }
+        if format_tag != WAVE_FORMAT_PCM {
+            return Err(WavError::UnsupportedFormat(format_tag));
+        }
+        if channels != 1 && channels != 2 {
+            return Err(WavError::UnsupportedChannels(channels));
+        }
+        if samples_per_second == 0 {
+            return Err(WavError::InvalidSampleRate(samples_per_second));
+        }
+
+        let mut samples: Vec<i16> = match bits_per_sample {
+            8 => sample_data
+                .iter()
+                .map(|&sample| ((sample as i16) - 128) << 8)
+                .collect(),
+            16 => sample_data
+                .chunks_exact(2)
+                .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+                .collect(),
+            bits => return Err(WavError::UnsupportedBitsPerSample(bits)),
+        };
+        // Drop a trailing partial frame.
+        samples.truncate(samples.len() - samples.len() % channels as usize);
+        Ok(Self {
+            samples_per_second,
+            channels,
+            samples: Cow::Owned(samples),
+        })
+    }
+}
+
+impl<'a> LoadedSound<'a> {
+    /// Copies the sound into the assets of `memory`.
+    pub fn store(
+        &self,
+        memory: &mut GameMemory,
+    ) -> Result<StoredSound, ArenaError> {
+        Ok(StoredSound {
+            samples_per_second: self.samples_per_second,
+            channels: self.channels,
+            samples: memory.push_asset(&self.samples)?,
+        })
+    }
+
+    /// Number of samples per channel.
+    pub fn frame_count(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    /// The left and right sample of `frame`, mono sounds play on both.
+    pub fn frame(&self, frame: usize) -> (i16, i16) {
+        match self.channels {
+            1 => (self.samples[frame], self.samples[frame]),
+            _ => (self.samples[frame * 2], self.samples[frame * 2 + 1]),
+        }
+    }
+}
+
+/// Streams interleaved 16 bit PCM into a WAVE file.
+///
+/// The RIFF and data chunk sizes are written as zero up front and patched
+/// by `finish`, so the writer never holds more than one buffer of samples.
+pub struct WavWriter<W: std::io::Write + std::io::Seek> {
+    writer: W,
+    data_bytes: u32,
+}
+
+impl<W: std::io::Write + std::io::Seek> WavWriter<W> {
+    pub fn new(
+        mut writer: W,
+        samples_per_second: u32,
+        channels: u16,
+    ) -> std::io::Result<Self> {
+        let block_align = channels * 2;
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&samples_per_second.to_le_bytes());
+        header.extend_from_slice(
+            &(samples_per_second * block_align as u32).to_le_bytes(),
+        );
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        writer.write_all(&header)?;
+        Ok(Self {
+            writer,
+            data_bytes: 0,
+        })
+    }
+
+    /// Appends `samples`, failing without writing anything once the file
+    /// would grow past the 4GiB a RIFF size can describe.
+    pub fn write_samples(&mut self, samples: &[i16]) -> std::io::Result<()> {
+        let data_bytes = std::convert::TryFrom::try_from(samples.len() * 2)
+            .ok()
+            .and_then(|bytes| self.data_bytes.checked_add(bytes))
+            .filter(|&bytes| bytes <= u32::MAX - 36)
+            .ok_or_else(|| {
+                std::io::Error::other("WAVE data larger than 4GiB")
+            })?;
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        self.writer.write_all(&bytes)?;
+        self.data_bytes = data_bytes;
+        Ok(())
+    }
+
+    /// Patches the chunk sizes and hands back the underlying writer.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        use std::io::SeekFrom;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(36 + self.data_bytes).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_bytes.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
@@ -10,7 +10,10 @@ pub use winapi::shared::winerror::*;
 pub use winapi::um::dsound::*;
 pub use winapi::um::libloaderapi::*;
 pub use winapi::um::memoryapi::*;
+pub use winapi::um::mmsystem::*;
 pub use winapi::um::profileapi::*;
+pub use winapi::um::synchapi::*;
+pub use winapi::um::timeapi::*;
 pub use winapi::um::unknwnbase::*;
 pub use winapi::um::wingdi::*;
 pub use winapi::um::winnt::HRESULT;
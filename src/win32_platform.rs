@@ -0,0 +1,394 @@
+use crate::controller_manager::*;
+use crate::screen_buffer::*;
+use crate::sound_buffer::*;
+use crate::win32;
+use handmade_hero::audio_ring::{
+    AudioRingBuffer, AudioSyncMarker, AudioSyncMarkers, AudioWrite,
+};
+use handmade_hero::input::{self, GameControllerInput, GameInput};
+use handmade_hero::replay::InputLoop;
+use handmade_hero::timing::{Clock, FramePacer};
+
+impl From<&mut OffScreenBuffer> for handmade_hero::GameScreenBuffer {
+    fn from(screen_buffer: &mut OffScreenBuffer) -> Self {
+        // SAFETY: `resize_dib_section` allocated `pitch * height` bytes for
+        // `memory`, they stay alive until the next resize.
+        unsafe {
+            handmade_hero::GameScreenBuffer::new(
+                screen_buffer.memory.cast(),
+                screen_buffer.bytes_per_pixel,
+                screen_buffer.height,
+                screen_buffer.width,
+                screen_buffer.pitch,
+            )
+        }
+    }
+}
+const PERMANENT_STORAGE_SIZE: usize = 64 * 1024 * 1024;
+const TRANSIENT_STORAGE_SIZE: usize = 256 * 1024 * 1024;
+
+// Debug builds map game memory at the same address every run so pointers
+// inside it can be compared between runs and snapshots.
+#[cfg(all(debug_assertions, target_pointer_width = "64"))]
+const GAME_MEMORY_BASE_ADDRESS: usize = 2 * 1024 * 1024 * 1024 * 1024;
+#[cfg(not(all(debug_assertions, target_pointer_width = "64")))]
+const GAME_MEMORY_BASE_ADDRESS: usize = 0;
+
+// Used when the monitor does not report its refresh rate.
+const DEFAULT_REFRESH_HZ: i32 = 60;
+
+/// QueryPerformanceCounter with Sleep for waiting.
+struct Win32Clock {
+    counter_per_second: u64,
+    // Whether timeBeginPeriod made Sleep accurate to a millisecond, without
+    // that the pacer only spins.
+    sleep_is_granular: bool,
+}
+
+impl Clock for Win32Clock {
+    fn now(&self) -> u64 {
+        let mut counter = win32::LARGE_INTEGER::default();
+        unsafe {
+            win32::QueryPerformanceCounter(&mut counter);
+            *counter.QuadPart() as u64
+        }
+    }
+
+    fn ticks_per_second(&self) -> u64 {
+        self.counter_per_second
+    }
+
+    fn sleep(&mut self, seconds: f32) {
+        if self.sleep_is_granular {
+            unsafe { win32::Sleep((1000.0 * seconds) as u32) };
+        }
+    }
+}
+
+pub struct Win32Platform<'a> {
+    window: win32::HWND,
+    buffer: &'a mut OffScreenBuffer,
+    sound_output: SoundOutput,
+    controller_manager: ControllerManager,
+    game_memory: handmade_hero::GameMemory,
+    input_loop: InputLoop,
+    clock: Win32Clock,
+    frame_pacer: FramePacer,
+    audio_ring: AudioRingBuffer,
+    // The write worked out for this frame, `None` when the cursors could not
+    // be read.
+    audio_write: Option<AudioWrite>,
+    audio_sync_markers: AudioSyncMarkers,
+}
+
+impl<'a> Win32Platform<'a> {
+    pub fn new(
+        window: win32::HWND,
+        buffer: &'a mut OffScreenBuffer,
+        controller_manager: ControllerManager,
+    ) -> Self {
+        // Get get initial counter
+        let mut counter_per_second = win32::LARGE_INTEGER::default();
+        unsafe {
+            win32::QueryPerformanceFrequency(&mut counter_per_second);
+        }
+
+        // Ask for a 1ms scheduler granularity so the frame pacer can sleep.
+        let sleep_is_granular =
+            unsafe { win32::timeBeginPeriod(1) } == win32::TIMERR_NOERROR;
+        let clock = Win32Clock {
+            counter_per_second: unsafe { *counter_per_second.QuadPart() }
+                as u64,
+            sleep_is_granular,
+        };
+        let monitor_refresh_hz = unsafe {
+            let device_context = win32::GetDC(window);
+            let refresh_hz =
+                win32::GetDeviceCaps(device_context, win32::VREFRESH);
+            win32::ReleaseDC(window, device_context);
+            refresh_hz
+        };
+        let monitor_refresh_hz = if monitor_refresh_hz > 1 {
+            monitor_refresh_hz
+        } else {
+            DEFAULT_REFRESH_HZ
+        };
+        // Update at half the refresh rate so a frame has two vblanks of time.
+        let frame_pacer =
+            FramePacer::new(monitor_refresh_hz as f32 / 2.0, &clock);
+
+        let mut sound_output = SoundOutput::default();
+        sound_output.init_sound(window).unwrap();
+        let audio_ring = AudioRingBuffer::new(
+            sound_output.samples_per_second,
+            sound_output.bytes_per_sample,
+            sound_output.buffer_size,
+            sound_output.latency_sample_count,
+        );
+        let game_memory = unsafe {
+            let base = win32::VirtualAlloc(
+                GAME_MEMORY_BASE_ADDRESS as win32::LPVOID,
+                PERMANENT_STORAGE_SIZE + TRANSIENT_STORAGE_SIZE,
+                win32::MEM_COMMIT | win32::MEM_RESERVE,
+                win32::PAGE_READWRITE,
+            );
+            if base.is_null() {
+                panic!("Could Not Allocate Game memory");
+            }
+            // SAFETY: VirtualAlloc hands out zeroed, page aligned memory
+            // that is never freed before the process exits.
+            handmade_hero::GameMemory::new(
+                base.cast(),
+                PERMANENT_STORAGE_SIZE,
+                TRANSIENT_STORAGE_SIZE,
+            )
+        };
+        buffer.resize_dib_section(1280, 720);
+        sound_output.clear_sound_buffer();
+        unsafe {
+            (*sound_output.buffer).Play(0, 0, win32::DSBPLAY_LOOPING);
+        }
+
+        Self {
+            window,
+            buffer,
+            sound_output,
+            controller_manager,
+            game_memory,
+            input_loop: InputLoop::default(),
+            clock,
+            frame_pacer,
+            audio_ring,
+            audio_write: None,
+            // Half a second of frames.
+            audio_sync_markers: AudioSyncMarkers::new(
+                (monitor_refresh_hz as usize / 4).max(1),
+            ),
+        }
+    }
+
+    fn read_controllers(&self, input: &mut GameInput) {
+        let gamepads =
+            (win32::XUSER_MAX_COUNT as usize).min(input::MAX_CONTROLLERS - 1);
+        for i in 0..gamepads {
+            let controller = input.gamepad_mut(i);
+            let mut state = win32::XINPUT_STATE::default();
+            let state_result = self
+                .controller_manager
+                .get_x_input_state(i as u32, &mut state);
+            if state_result != win32::ERROR_SUCCESS {
+                // Controller is not connected
+                *controller = GameControllerInput::default();
+                continue;
+            }
+
+            let pad = state.Gamepad;
+            let down = |button| pad.wButtons & button != 0;
+            controller.is_connected = true;
+            controller.is_analog = true;
+            controller
+                .move_up
+                .process(down(win32::XINPUT_GAMEPAD_DPAD_UP));
+            controller
+                .move_down
+                .process(down(win32::XINPUT_GAMEPAD_DPAD_DOWN));
+            controller
+                .move_left
+                .process(down(win32::XINPUT_GAMEPAD_DPAD_LEFT));
+            controller
+                .move_right
+                .process(down(win32::XINPUT_GAMEPAD_DPAD_RIGHT));
+            controller
+                .action_down
+                .process(down(win32::XINPUT_GAMEPAD_A));
+            controller
+                .action_right
+                .process(down(win32::XINPUT_GAMEPAD_B));
+            controller
+                .action_left
+                .process(down(win32::XINPUT_GAMEPAD_X));
+            controller.action_up.process(down(win32::XINPUT_GAMEPAD_Y));
+            controller
+                .left_shoulder
+                .process(down(win32::XINPUT_GAMEPAD_LEFT_SHOULDER));
+            controller
+                .right_shoulder
+                .process(down(win32::XINPUT_GAMEPAD_RIGHT_SHOULDER));
+            controller.start.process(down(win32::XINPUT_GAMEPAD_START));
+            controller.back.process(down(win32::XINPUT_GAMEPAD_BACK));
+
+            let left_deadzone = win32::XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE;
+            let right_deadzone = win32::XINPUT_GAMEPAD_RIGHT_THUMB_DEADZONE;
+            controller.left_stick.process(
+                normalize_stick(pad.sThumbLX, left_deadzone),
+                normalize_stick(pad.sThumbLY, left_deadzone),
+            );
+            controller.right_stick.process(
+                normalize_stick(pad.sThumbRX, right_deadzone),
+                normalize_stick(pad.sThumbRY, right_deadzone),
+            );
+        }
+    }
+
+    fn process_key(
+        &mut self,
+        keyboard: &mut GameControllerInput,
+        vk_code: i32,
+        l_param: win32::LPARAM,
+    ) {
+        let was_down = (l_param & (1 << 30)) != 0;
+        let is_down = (l_param & (1 << 31)) == 0;
+        let is_alt_key_down = (l_param & (1 << 29)) != 0;
+
+        if vk_code == win32::VK_F4 && is_alt_key_down {
+            unsafe { crate::running = false };
+        }
+        // Auto repeat sends more key downs while the key is held.
+        if was_down == is_down {
+            return;
+        }
+        let button = match vk_code {
+            win32::VK_UP => &mut keyboard.move_up,
+            win32::VK_DOWN => &mut keyboard.move_down,
+            win32::VK_LEFT => &mut keyboard.move_left,
+            win32::VK_RIGHT => &mut keyboard.move_right,
+            win32::VK_SPACE => &mut keyboard.start,
+            win32::VK_ESCAPE => &mut keyboard.back,
+            // L records an input loop, plays it back and stops it again.
+            0x4c => {
+                if is_down {
+                    self.input_loop.toggle(&mut self.game_memory);
+                }
+                return;
+            }
+            _ => return,
+        };
+        button.process(is_down);
+    }
+}
+
+/// Maps a raw XInput stick axis to -1.0..=1.0, reading zero inside the
+/// deadzone and starting from zero just outside of it.
+fn normalize_stick(value: i16, deadzone: i16) -> f32 {
+    let value = value as f32;
+    let deadzone = deadzone as f32;
+    if value < -deadzone {
+        (value + deadzone) / (32768.0 - deadzone)
+    } else if value > deadzone {
+        (value - deadzone) / (32767.0 - deadzone)
+    } else {
+        0.0
+    }
+}
+
+impl<'a> handmade_hero::Platform for Win32Platform<'a> {
+    fn poll_input(&mut self, input: &mut GameInput) -> bool {
+        let keyboard = input.keyboard_mut();
+        keyboard.is_connected = true;
+        let mut msg: win32::MSG = win32::MSG::default();
+        unsafe {
+            while win32::PeekMessageA(
+                &mut msg,
+                self.window,
+                0,
+                0,
+                win32::PM_REMOVE,
+            ) != 0
+            {
+                match msg.message {
+                    win32::WM_SYSKEYUP
+                    | win32::WM_SYSKEYDOWN
+                    | win32::WM_KEYUP
+                    | win32::WM_KEYDOWN => self.process_key(
+                        keyboard,
+                        msg.wParam as i32,
+                        msg.lParam,
+                    ),
+                    _ => {
+                        win32::TranslateMessage(&msg);
+                        win32::DispatchMessageA(&msg);
+                    }
+                }
+            }
+            if !crate::running {
+                return false;
+            }
+        }
+
+        // Get input state
+        self.read_controllers(input);
+        self.input_loop.process(input, &mut self.game_memory);
+        true
+    }
+
+    fn game_memory(&mut self) -> &mut handmade_hero::GameMemory {
+        &mut self.game_memory
+    }
+
+    fn back_buffer(&mut self) -> handmade_hero::GameScreenBuffer {
+        (&mut *self.buffer).into()
+    }
+
+    fn samples_per_second(&self) -> u32 {
+        self.sound_output.samples_per_second
+    }
+
+    fn sound_samples_needed(&mut self) -> u32 {
+        let mut play_cursor = 0;
+        let mut write_cursor = 0;
+        self.audio_write = if win32::SUCCEEDED(unsafe {
+            (*self.sound_output.buffer)
+                .GetCurrentPosition(&mut play_cursor, &mut write_cursor)
+        }) {
+            let write = self.audio_ring.next_write(play_cursor, write_cursor);
+            self.audio_sync_markers.record(AudioSyncMarker::new(
+                play_cursor,
+                write_cursor,
+                &write,
+            ));
+            Some(write)
+        } else {
+            None
+        };
+        self.audio_write.map_or(0, |write| write.sample_count)
+    }
+
+    fn submit_audio(&mut self, samples: &[i16]) {
+        if let Some(write) = self.audio_write.take() {
+            if write.bytes_to_write > 0 {
+                self.sound_output.fill_sound_buffer(&write, samples);
+            }
+            self.audio_ring.commit(&write);
+        }
+        unsafe {
+            (*self.sound_output.buffer).Play(0, 0, win32::DSBPLAY_LOOPING);
+        }
+    }
+
+    fn wait_for_frame_end(&mut self) -> bool {
+        self.frame_pacer.wait_for_frame_end(&mut self.clock).missed
+    }
+
+    fn present_frame(&mut self) {
+        if cfg!(debug_assertions) {
+            let mut video_buffer: handmade_hero::GameScreenBuffer =
+                (&mut *self.buffer).into();
+            self.audio_sync_markers
+                .draw(&mut video_buffer, self.sound_output.buffer_size);
+        }
+        unsafe {
+            let device_context = win32::GetDC(self.window);
+            let window_dimensions = crate::get_window_dimensions(self.window);
+            self.buffer.update_window(
+                device_context,
+                window_dimensions.width,
+                window_dimensions.height,
+            );
+            win32::ReleaseDC(self.window, device_context);
+        }
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &self.clock
+    }
+}
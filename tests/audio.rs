@@ -0,0 +1,103 @@
+use handmade_hero::envelope::Adsr;
+use handmade_hero::oscillator::Waveform;
+use handmade_hero::sequencer::{
+    Cell, Instrument, InstrumentId, Pattern, Sequencer, Song,
+};
+use handmade_hero::{GameAudioBuffer, GameMemory, Music};
+
+const SAMPLES_PER_SECOND: u32 = 48000;
+
+fn get_sound_samples(memory: &mut GameMemory, samples: &mut [i16]) {
+    let mut audio_buffer = GameAudioBuffer::new(SAMPLES_PER_SECOND, samples);
+    handmade_hero::game_get_sound_samples(memory, &mut audio_buffer);
+}
+
+fn warmed_up_memory() -> GameMemory {
+    let mut memory = GameMemory::allocate(1 << 16, 0);
+    memory.state().y_offset = 300;
+    let song = arpeggio().store(&mut memory).unwrap();
+    memory.state().music = Some(Music::Song(Sequencer::new(song)));
+    // The first call ramps the tone in, the rest play it at full volume.
+    get_sound_samples(&mut memory, &mut [0; 2 * 64]);
+    memory
+}
+
+// C major over and over, a row every 50ms.
+fn arpeggio() -> Song {
+    let mut pattern = Pattern::new(4, 1);
+    for (row, &key) in [60, 64, 67, 72].iter().enumerate() {
+        pattern.set(
+            row,
+            0,
+            Cell::Note {
+                key,
+                instrument: InstrumentId(0),
+                velocity: 1.0,
+            },
+        );
+    }
+    Song {
+        bpm: 300.0,
+        rows_per_beat: 4,
+        instruments: vec![Instrument::Synth {
+            waveform: Waveform::Square { pulse_width: 0.5 },
+            adsr: Adsr::new(0.005, 0.01, 0.6, 0.02),
+            volume: 0.1,
+        }],
+        patterns: vec![pattern],
+        order: vec![0],
+        loop_range: Some(0..1),
+    }
+}
+
+#[test]
+fn samples_do_not_depend_on_how_they_are_asked_for() {
+    let mut whole = vec![0; 2 * 4800];
+    get_sound_samples(&mut warmed_up_memory(), &mut whole);
+    assert!(whole.iter().any(|&sample| sample != 0));
+
+    let mut memory = warmed_up_memory();
+    let mut chunked = vec![0; 2 * 4800];
+    let mut rest = &mut chunked[..];
+    for &count in [1, 7, 800, 0, 1600, 333].iter().cycle() {
+        if rest.is_empty() {
+            break;
+        }
+        let count = (2 * count).min(rest.len());
+        let (chunk, tail) = rest.split_at_mut(count);
+        get_sound_samples(&mut memory, chunk);
+        rest = tail;
+    }
+    assert_eq!(whole, chunked);
+}
+
+#[test]
+fn empty_buffers_leave_the_sound_where_it_was() {
+    let mut memory = warmed_up_memory();
+    let mut expected = vec![0; 2 * 256];
+    get_sound_samples(&mut warmed_up_memory(), &mut expected);
+
+    get_sound_samples(&mut memory, &mut []);
+    let mut samples = vec![0; 2 * 256];
+    get_sound_samples(&mut memory, &mut samples);
+    assert_eq!(samples, expected);
+}
+
+#[test]
+fn music_plays_on_from_a_copy_of_permanent_storage() {
+    let mut memory = warmed_up_memory();
+    get_sound_samples(&mut memory, &mut [0; 2 * 1000]);
+    // The sequencer and mixer state are plain bytes, so a byte for byte copy
+    // of them carries on mid song exactly where the original does.
+    let mut copy = GameMemory::allocate(1 << 16, 0);
+    copy.permanent_storage_mut()
+        .copy_from_slice(memory.permanent_storage());
+    copy.is_initialized = true;
+
+    let mut expected = vec![0; 2 * 4800];
+    get_sound_samples(&mut memory, &mut expected);
+    drop(memory);
+    let mut samples = vec![0; 2 * 4800];
+    get_sound_samples(&mut copy, &mut samples);
+    assert_eq!(samples, expected);
+}
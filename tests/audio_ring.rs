@@ -0,0 +1,220 @@
+use handmade_hero::audio_ring::{
+    AudioRingBuffer, AudioSyncMarker, AudioSyncMarkers, AudioWrite,
+    SimulatedAudioDevice,
+};
+use handmade_hero::timing::FakeClock;
+
+const BYTES_PER_SAMPLE: u32 = 4;
+
+/// Whether byte `offset` lies in the ring region from `start` up to `end`.
+fn in_region(offset: u32, start: u32, end: u32) -> bool {
+    if start <= end {
+        (start..end).contains(&offset)
+    } else {
+        offset >= start || offset < end
+    }
+}
+
+/// A ring that already wrote or skipped `samples` samples.
+fn ring_at(size: u32, latency: u32, samples: u32) -> AudioRingBuffer {
+    let mut ring = AudioRingBuffer::new(48000, BYTES_PER_SAMPLE, size, latency);
+    ring.commit(&AudioWrite {
+        lock_offset: 0,
+        bytes_to_write: 0,
+        sample_count: 0,
+        target_cursor: 0,
+        skipped_samples: samples,
+    });
+    ring
+}
+
+#[test]
+fn writes_never_touch_committed_samples() {
+    let samples = 32;
+    let size = samples * BYTES_PER_SAMPLE;
+    let latency = 6;
+    // Every gap between the cursors that keeps the target within half the
+    // ring, every play cursor and every write position.
+    for lead in 0..samples / 2 - latency {
+        for play in 0..samples {
+            for written in 0..samples {
+                let ring = ring_at(size, latency, written);
+                let play_cursor = play * BYTES_PER_SAMPLE;
+                let write_cursor = (play + lead) % samples * BYTES_PER_SAMPLE;
+                let write = ring.next_write(play_cursor, write_cursor);
+                let context = format!(
+                    "lead {} play {} written {}: {:?}",
+                    lead, play, written, write
+                );
+
+                assert_eq!(
+                    write.sample_count * BYTES_PER_SAMPLE,
+                    write.bytes_to_write,
+                    "{}",
+                    context
+                );
+                assert!(
+                    write.bytes_to_write <= (lead + latency) * BYTES_PER_SAMPLE,
+                    "{}",
+                    context
+                );
+                assert_eq!(
+                    write.target_cursor,
+                    (play + lead + latency) % samples * BYTES_PER_SAMPLE,
+                    "{}",
+                    context
+                );
+                for byte in ring.regions(&write).iter().cloned().flatten() {
+                    assert!(
+                        !in_region(byte as u32, play_cursor, write_cursor),
+                        "{} writes committed byte {}",
+                        context,
+                        byte
+                    );
+                }
+                if write.skipped_samples > 0 {
+                    assert_eq!(write.lock_offset, write_cursor, "{}", context);
+                }
+                if write.bytes_to_write > 0 {
+                    let end = (write.lock_offset + write.bytes_to_write) % size;
+                    assert_eq!(end, write.target_cursor, "{}", context);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn regions_split_where_the_ring_wraps() {
+    let ring = AudioRingBuffer::new(48000, BYTES_PER_SAMPLE, 64, 4);
+    let mut write = ring.next_write(0, 8);
+    assert_eq!(write.lock_offset, 8);
+    assert_eq!(write.bytes_to_write, 16);
+    assert_eq!(ring.regions(&write), [8..24, 0..0]);
+
+    write.lock_offset = 56;
+    assert_eq!(ring.regions(&write), [56..64, 0..8]);
+}
+
+/// Runs a game loop of `frame_seconds` long frames against a device
+/// playing in real time and returns the smallest number of bytes the
+/// writes were ahead of the play cursor.
+fn run_frames(
+    frame_seconds: &[f32],
+    cursor_granularity: u32,
+) -> (i64, AudioRingBuffer, SimulatedAudioDevice) {
+    let samples_per_second = 48000;
+    let mut clock = FakeClock::new(1_000_000);
+    clock.ticks_per_read = 0;
+    let mut ring = AudioRingBuffer::new(
+        samples_per_second,
+        BYTES_PER_SAMPLE,
+        samples_per_second * BYTES_PER_SAMPLE,
+        samples_per_second / 15,
+    );
+    let mut device = SimulatedAudioDevice::new(
+        samples_per_second,
+        BYTES_PER_SAMPLE,
+        ring.buffer_size,
+        &clock,
+    );
+    device.cursor_granularity = cursor_granularity;
+    let mut smallest_lead = i64::MAX;
+    for &seconds in frame_seconds {
+        let (play_cursor, write_cursor) = device.current_position(&clock);
+        let write = ring.next_write(play_cursor, write_cursor);
+        let samples = vec![1i16; write.sample_count as usize * 2];
+        device.write(&ring, &write, &samples);
+        ring.commit(&write);
+
+        clock.advance(seconds);
+        let written =
+            ring.running_sample_index() as i64 * BYTES_PER_SAMPLE as i64;
+        let played = device.played_bytes(&clock) as i64;
+        smallest_lead = smallest_lead.min(written - played);
+        assert!(
+            written - played <= ring.buffer_size as i64,
+            "wrote over samples that were not played yet"
+        );
+    }
+    (smallest_lead, ring, device)
+}
+
+#[test]
+fn jittery_frames_shorter_than_the_latency_never_underrun() {
+    // Frames between 20ms and 60ms in a fixed pseudo random order.
+    let mut seed = 1u32;
+    let frames: Vec<f32> = (0..2000)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            0.020 + (seed >> 16) as f32 % 41.0 / 1000.0
+        })
+        .collect();
+    for &granularity in &[BYTES_PER_SAMPLE, 480 * BYTES_PER_SAMPLE] {
+        let (smallest_lead, ring, _) = run_frames(&frames, granularity);
+        assert!(
+            smallest_lead > 0,
+            "underran by {} bytes with a granularity of {}",
+            -smallest_lead,
+            granularity
+        );
+        assert!(ring.running_sample_index() > 0);
+    }
+}
+
+#[test]
+fn stalls_skip_ahead_to_the_write_cursor() {
+    let frames = [0.033, 0.033, 0.300, 0.033, 0.033];
+    let (smallest_lead, ring, device) = run_frames(&frames, BYTES_PER_SAMPLE);
+    // The 300ms frame outran the 66ms of latency once, the frame after it
+    // skipped to the write cursor and wrote ahead again.
+    assert!(smallest_lead < 0);
+    let played = (48000.0 * frames.iter().sum::<f32>()) as i64;
+    let ahead = ring.running_sample_index() as i64 - played;
+    assert!(ahead > 0, "{} samples behind", -ahead);
+    let lead = device.write_cursor_lead / BYTES_PER_SAMPLE;
+    assert!(ahead <= (lead + ring.latency_sample_count) as i64);
+}
+
+#[test]
+fn device_plays_what_was_written() {
+    let mut clock = FakeClock::new(1000);
+    clock.ticks_per_read = 0;
+    let mut ring = AudioRingBuffer::new(1000, BYTES_PER_SAMPLE, 80, 2);
+    let mut device =
+        SimulatedAudioDevice::new(1000, BYTES_PER_SAMPLE, 80, &clock);
+    device.write_cursor_lead = 2 * BYTES_PER_SAMPLE;
+
+    clock.advance(0.003);
+    let (play_cursor, write_cursor) = device.current_position(&clock);
+    assert_eq!((play_cursor, write_cursor), (12, 20));
+    let write = ring.next_write(play_cursor, write_cursor);
+    // Three samples were played before anything was written, the write
+    // starts at the write cursor.
+    assert_eq!(write.skipped_samples, 5);
+    assert_eq!(write.lock_offset, 20);
+    assert_eq!(write.sample_count, 2);
+    device.write(&ring, &write, &[1, -1, 2, -2]);
+    ring.commit(&write);
+    assert_eq!(ring.running_sample_index(), 7);
+    assert_eq!(&device.buffer()[20..28], &[1, 0, 255, 255, 2, 0, 254, 255]);
+    assert!(device.buffer()[..20].iter().all(|&byte| byte == 0));
+    assert!(device.buffer()[28..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn sync_markers_keep_the_latest_frames() {
+    let mut markers = AudioSyncMarkers::new(3);
+    assert!(markers.latest().is_none());
+    for cursor in 0..5 {
+        markers.record(AudioSyncMarker {
+            play_cursor: cursor,
+            write_cursor: cursor + 1,
+            target_cursor: cursor + 2,
+        });
+    }
+    let play_cursors: Vec<u32> =
+        markers.iter().map(|marker| marker.play_cursor).collect();
+    assert_eq!(play_cursors, [2, 3, 4]);
+    assert_eq!(markers.latest().unwrap().target_cursor, 6);
+}
@@ -0,0 +1,106 @@
+use handmade_hero::bitmap::{BitmapError, LoadedBitmap};
+
+// A 24 bit BMP of `rows` of `0xRRGGBB` pixels, bottom-up unless `height` is
+// negative.
+fn bmp(width: i32, height: i32, compression: u32, rows: &[&[u32]]) -> Vec<u8> {
+    let mut pixels = Vec::new();
+    for row in rows {
+        for &pixel in row.iter() {
+            pixels.extend_from_slice(&pixel.to_le_bytes()[..3]);
+        }
+        while pixels.len() % 4 != 0 {
+            pixels.push(0);
+        }
+    }
+    let mut data = Vec::new();
+    data.extend_from_slice(b"BM");
+    data.extend_from_slice(&(54 + pixels.len() as u32).to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&54u32.to_le_bytes());
+    data.extend_from_slice(&40u32.to_le_bytes());
+    data.extend_from_slice(&width.to_le_bytes());
+    data.extend_from_slice(&height.to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&24u16.to_le_bytes());
+    data.extend_from_slice(&compression.to_le_bytes());
+    data.extend_from_slice(&[0; 20]);
+    data.extend_from_slice(&pixels);
+    data
+}
+
+#[test]
+fn bottom_up_and_top_down_files_load_the_same() {
+    let top: &[u32] = &[0x10_2030, 0x40_5060, 0x70_8090];
+    let bottom: &[u32] = &[0xa0_b0c0, 0xd0_e0f0, 0x01_0203];
+    let bottom_up = LoadedBitmap::from_bmp(&bmp(3, 2, 0, &[bottom, top]));
+    let top_down = LoadedBitmap::from_bmp(&bmp(3, -2, 0, &[top, bottom]));
+    let bitmap = bottom_up.unwrap();
+    assert_eq!(top_down.unwrap(), bitmap);
+    assert_eq!((bitmap.width, bitmap.height), (3, 2));
+    // Files without alpha are opaque.
+    assert_eq!(bitmap.row(0), &[0xff10_2030, 0xff40_5060, 0xff70_8090]);
+    assert_eq!(bitmap.row(1), &[0xffa0_b0c0, 0xffd0_e0f0, 0xff01_0203]);
+}
+
+#[test]
+fn unsupported_files_are_rejected() {
+    let row: &[u32] = &[0, 0];
+    let parse = |data: &[u8]| LoadedBitmap::from_bmp(data).unwrap_err();
+    assert_eq!(parse(b"PNG"), BitmapError::NotABitmap);
+    // Run length encoded.
+    assert_eq!(
+        parse(&bmp(2, 1, 1, &[row])),
+        BitmapError::UnsupportedCompression(1)
+    );
+    assert_eq!(
+        parse(&bmp(2, -1, 2, &[row])),
+        BitmapError::UnsupportedCompression(2)
+    );
+    assert_eq!(
+        parse(&bmp(2, 0, 0, &[])),
+        BitmapError::InvalidDimensions(2, 0)
+    );
+    assert_eq!(
+        parse(&bmp(2, i32::MIN, 0, &[])),
+        BitmapError::InvalidDimensions(2, i32::MIN)
+    );
+
+    let mut data = bmp(2, 1, 0, &[row]);
+    data[28] = 8;
+    assert_eq!(parse(&data), BitmapError::UnsupportedBitCount(8));
+    let mut data = bmp(2, 1, 0, &[row]);
+    data[14] = 12;
+    assert_eq!(parse(&data), BitmapError::UnsupportedHeader(12));
+}
+
+#[test]
+fn truncated_files_are_rejected() {
+    let data = bmp(2, 2, 0, &[&[1, 2], &[3, 4]]);
+    assert_eq!(
+        LoadedBitmap::from_bmp(&data[..data.len() - 1]),
+        Err(BitmapError::Truncated)
+    );
+    assert_eq!(
+        LoadedBitmap::from_bmp(&data[..20]),
+        Err(BitmapError::Truncated)
+    );
+    let mut past_the_end = data.clone();
+    past_the_end[10..14].copy_from_slice(&(data.len() as u32).to_le_bytes());
+    assert_eq!(
+        LoadedBitmap::from_bmp(&past_the_end),
+        Err(BitmapError::Truncated)
+    );
+}
+
+#[test]
+fn sizes_too_large_for_memory_are_rejected() {
+    let mut data = bmp(i32::MAX, -i32::MAX, 0, &[]);
+    data[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+    let error = LoadedBitmap::from_bmp(&data).unwrap_err();
+    if cfg!(target_pointer_width = "64") {
+        // The pixels would end past the end of the file.
+        assert_eq!(error, BitmapError::Truncated);
+    } else {
+        assert_eq!(error, BitmapError::InvalidDimensions(i32::MAX, i32::MAX));
+    }
+}
@@ -0,0 +1,192 @@
+use handmade_hero::envelope::{Adsr, Envelope, Stage};
+use handmade_hero::mixer::{Mixer, NoteEvent, Voice, MAX_NOTE_EVENTS};
+use handmade_hero::oscillator::{Waveform, Wavetable, WavetableId};
+use handmade_hero::GameAudioBuffer;
+
+fn levels(envelope: &mut Envelope, count: usize) -> Vec<f32> {
+    (0..count).map(|_| envelope.next_level(1000.0)).collect()
+}
+
+fn assert_close(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+    }
+}
+
+#[test]
+fn envelopes_go_through_every_stage() {
+    let mut envelope = Envelope::new(Adsr::new(0.004, 0.002, 0.5, 0.004));
+    assert_eq!(levels(&mut envelope, 2), [0.0, 0.0]);
+    assert_eq!(envelope.stage(), Stage::Idle);
+
+    envelope.note_on(0.8);
+    assert_close(&levels(&mut envelope, 4), &[0.2, 0.4, 0.6, 0.8]);
+    assert_close(&levels(&mut envelope, 5), &[0.6, 0.4, 0.4, 0.4, 0.4]);
+    assert_eq!(envelope.stage(), Stage::Sustain);
+
+    envelope.note_off();
+    assert_close(&levels(&mut envelope, 5), &[0.3, 0.2, 0.1, 0.0, 0.0]);
+    assert_eq!(envelope.stage(), Stage::Done);
+    assert_eq!(levels(&mut envelope, 2), [0.0, 0.0]);
+}
+
+#[test]
+fn notes_cut_short_continue_from_the_current_level() {
+    let adsr = Adsr::new(0.01, 0.01, 0.5, 0.01);
+    let mut envelope = Envelope::new(adsr);
+    let mut trace = vec![0.0];
+    envelope.note_on(1.0);
+    trace.extend(levels(&mut envelope, 5));
+    // Released halfway up the attack, then started again quietly halfway
+    // down the release, then again louder.
+    envelope.note_off();
+    trace.extend(levels(&mut envelope, 5));
+    envelope.note_on(0.1);
+    trace.extend(levels(&mut envelope, 30));
+    envelope.note_on(1.0);
+    trace.extend(levels(&mut envelope, 30));
+    envelope.note_off();
+    trace.extend(levels(&mut envelope, 30));
+
+    assert_eq!(envelope.stage(), Stage::Done);
+    assert_eq!(*trace.last().unwrap(), 0.0);
+    // Never more than a full scale attack step at a time.
+    for pair in trace.windows(2) {
+        assert!((pair[1] - pair[0]).abs() <= 0.1 + 1e-6, "{:?}", pair);
+    }
+
+    // A note without attack or release starts and stops on the sample.
+    let mut envelope = Envelope::new(Adsr::new(0.0, 0.0, 1.0, 0.0));
+    envelope.note_on(0.5);
+    assert_eq!(levels(&mut envelope, 2), [0.5, 0.5]);
+    envelope.note_off();
+    assert_eq!(levels(&mut envelope, 1), [0.0]);
+}
+
+// A voice playing a constant 1.0, so the output is its envelope.
+fn constant_voice() -> (Voice, [Wavetable<'static>; 1]) {
+    let voice = Voice::oscillator(
+        Waveform::Wavetable(WavetableId(0)),
+        100.0,
+        1.0,
+        -1.0,
+    )
+    .with_envelope(Adsr::new(0.0, 0.0, 1.0, 0.0));
+    (voice, [Wavetable::new(&[1.0, 1.0])])
+}
+
+fn left_channel(
+    mixer: &mut Mixer,
+    wavetables: &[Wavetable],
+    frames: &[usize],
+) -> Vec<i16> {
+    let mut left = Vec::new();
+    for &count in frames {
+        let mut samples = vec![0; 2 * count];
+        mixer.mix(
+            &mut GameAudioBuffer::new(48000, &mut samples),
+            &[],
+            wavetables,
+        );
+        left.extend(samples.iter().step_by(2));
+    }
+    left
+}
+
+#[test]
+fn note_events_land_on_their_frame_however_the_mix_is_split() {
+    let render = |frames: &[usize]| {
+        let (voice, wavetables) = constant_voice();
+        let mut mixer = Mixer::default();
+        let id = mixer.play(voice).unwrap();
+        assert!(mixer.note_on(id, 100, 100.0, 0.5));
+        assert!(mixer.note_off(id, 300));
+        assert!(mixer.note_on(id, 299, 100.0, 1.0));
+        assert!(mixer.schedule(
+            id,
+            1000,
+            NoteEvent::On {
+                frequency: 100.0,
+                velocity: 0.25,
+            }
+        ));
+        assert!(mixer.note_off(id, 1001));
+        let left = left_channel(&mut mixer, &wavetables, frames);
+        assert!(!mixer.is_playing(id));
+        left
+    };
+
+    let whole = render(&[1200]);
+    let half = i16::MAX / 2;
+    assert!(whole[..100].iter().all(|&sample| sample == 0));
+    assert!(whole[100..299].iter().all(|&sample| sample == half));
+    assert_eq!(whole[299], i16::MAX);
+    assert!(whole[300..1000].iter().all(|&sample| sample == 0));
+    assert_eq!(whole[1000], i16::MAX / 4);
+    assert!(whole[1001..].iter().all(|&sample| sample == 0));
+
+    assert_eq!(render(&[1, 99, 1, 199, 300, 256, 257, 87]), whole);
+    assert_eq!(render(&[0, 1000, 0, 1, 199]), whole);
+}
+
+#[test]
+fn released_voices_free_their_slot() {
+    let (voice, wavetables) = constant_voice();
+    let mut mixer = Mixer::default();
+    // A voice that never gets a note is freed by the next mix.
+    let id = mixer.play(voice).unwrap();
+    left_channel(&mut mixer, &wavetables, &[64]);
+    assert!(!mixer.is_playing(id));
+    assert_eq!(mixer.active_voices(), 0);
+
+    let id = mixer.play(voice).unwrap();
+    assert!(mixer.note_on(id, 0, 100.0, 1.0));
+    assert!(mixer.note_off(id, 10));
+    // A note waiting for a later mix keeps it.
+    assert!(mixer.note_on(id, 100, 100.0, 1.0));
+    assert!(mixer.note_off(id, 110));
+    left_channel(&mut mixer, &wavetables, &[64]);
+    assert!(mixer.is_playing(id));
+    let left = left_channel(&mut mixer, &wavetables, &[64]);
+    assert_eq!(left.iter().filter(|&&sample| sample != 0).count(), 10);
+    assert!(!mixer.is_playing(id));
+    assert!(!mixer.note_on(id, 0, 100.0, 1.0));
+    assert_eq!(mixer.active_voices(), 0);
+}
+
+#[test]
+fn the_note_queue_is_bounded() {
+    let (voice, _) = constant_voice();
+    let mut mixer = Mixer::default();
+    let id = mixer.play(voice).unwrap();
+    for frame in 0..MAX_NOTE_EVENTS as u32 {
+        assert!(mixer.note_on(id, frame, 100.0, 1.0));
+    }
+    assert!(!mixer.note_off(id, 0));
+    // A voice that can't get its note isn't left behind.
+    let other = mixer.play(voice).unwrap();
+    assert!(!mixer.note_on(other, 0, 100.0, 1.0));
+    left_channel(&mut mixer, &[], &[1]);
+    assert!(!mixer.is_playing(other));
+    assert_eq!(mixer.play_note(voice, 0, 10, 100.0, 1.0), None);
+    assert_eq!(mixer.active_voices(), 1);
+
+    // Stopping the voice drops its notes.
+    mixer.stop(id);
+    left_channel(&mut mixer, &[], &[1]);
+    let id = mixer.play(voice).unwrap();
+    assert!(mixer.note_on(id, 0, 100.0, 1.0));
+}
+
+#[test]
+fn sound_effects_play_one_note() {
+    let (voice, wavetables) = constant_voice();
+    let mut mixer = Mixer::default();
+    let id = mixer.play_note(voice, 5, 20, 440.0, 1.0).unwrap();
+    let left = left_channel(&mut mixer, &wavetables, &[16, 16]);
+    let sounding: Vec<usize> =
+        (0..left.len()).filter(|&i| left[i] != 0).collect();
+    assert_eq!(sounding, (5..25).collect::<Vec<_>>());
+    assert!(!mixer.is_playing(id));
+}
@@ -0,0 +1,94 @@
+use handmade_hero::timing::FrameTime;
+use handmade_hero::FrameStats;
+
+fn frame(ms: f32) -> FrameTime {
+    FrameTime {
+        seconds: ms / 1000.0,
+        cycles: (ms * 1_000_000.0) as u64,
+    }
+}
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 1e-3,
+        "{} is not {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn summary_covers_only_the_window() {
+    let mut stats = FrameStats::new(10);
+    assert!(stats.summary().is_none());
+    // A slow start that falls out of the window.
+    for _ in 0..5 {
+        stats.record(frame(100.0), true);
+    }
+    for ms in 1..=10 {
+        stats.record(frame(ms as f32), ms == 10);
+    }
+    let summary = stats.summary().unwrap();
+    assert_eq!(summary.frames, 10);
+    assert_close(summary.min_ms, 1.0);
+    assert_close(summary.max_ms, 10.0);
+    assert_close(summary.mean_ms, 5.5);
+    assert_close(summary.p50_ms, 5.0);
+    assert_close(summary.p90_ms, 9.0);
+    assert_close(summary.p99_ms, 10.0);
+    assert_close(summary.mean_mega_cycles, 5.5);
+    assert_eq!(summary.missed_vsyncs, 1);
+
+    assert_eq!(stats.frames(), 15);
+    assert_eq!(stats.missed_vsyncs(), 6);
+    let recent: Vec<u64> = stats.recent().map(|frame| frame.frame).collect();
+    assert_eq!(recent, (5..15).collect::<Vec<_>>());
+}
+
+#[test]
+fn percentiles_use_the_nearest_rank() {
+    let mut stats = FrameStats::new(4);
+    for &ms in &[40.0, 10.0, 30.0, 20.0] {
+        stats.record(frame(ms), false);
+    }
+    assert_close(stats.percentile(0.0).unwrap(), 10.0);
+    assert_close(stats.percentile(25.0).unwrap(), 10.0);
+    assert_close(stats.percentile(26.0).unwrap(), 20.0);
+    assert_close(stats.percentile(75.0).unwrap(), 30.0);
+    assert_close(stats.percentile(100.0).unwrap(), 40.0);
+}
+
+#[test]
+fn long_frames_land_in_the_last_bucket() {
+    let mut stats = FrameStats::new(8);
+    for &ms in &[1.0, 4.9, 5.5, 16.6, 33.3, 34.0, 250.0] {
+        stats.record(frame(ms), false);
+    }
+    assert_eq!(stats.histogram(5.0, 8), vec![2, 1, 0, 1, 0, 0, 2, 1]);
+    assert!(stats.histogram(5.0, 0).is_empty());
+}
+
+#[test]
+fn csv_holds_every_frame_with_history() {
+    let mut stats = FrameStats::new(2).with_history();
+    stats.record(frame(16.5), false);
+    stats.record(frame(40.0), true);
+    stats.record(frame(17.0), false);
+    let mut csv = Vec::new();
+    stats.write_csv(&mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "frame,ms,mega_cycles,missed_vsync\n\
+         0,16.5000,16.5000,0\n\
+         1,40.0000,40.0000,1\n\
+         2,17.0000,17.0000,0\n"
+    );
+
+    let mut windowed = FrameStats::new(2);
+    windowed.record(frame(16.5), false);
+    windowed.record(frame(40.0), true);
+    windowed.record(frame(17.0), false);
+    let mut csv = Vec::new();
+    windowed.write_csv(&mut csv).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 3);
+}
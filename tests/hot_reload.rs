@@ -0,0 +1,68 @@
+#![cfg(unix)]
+
+// The headless runner's loader, built into this test as it is into the
+// runner.
+#[path = "../src/bin/handmade_headless/game_library.rs"]
+mod game_library;
+
+use game_library::GameLibrary;
+use handmade_hero::{GameInput, GameMemory, GameScreenBuffer};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// The cdylib cargo built along with this test, in the directory above its
+// `deps`.
+fn built_library() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    exe.parent().and_then(Path::parent).unwrap().join(format!(
+        "{}handmade_hero{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    ))
+}
+
+// Runs a frame of the loaded game holding right, returning the x offset.
+fn run_frame(library: &GameLibrary, memory: &mut GameMemory) -> i32 {
+    let mut input = GameInput::default();
+    let keyboard = input.keyboard_mut();
+    keyboard.is_connected = true;
+    keyboard.move_right.process(true);
+    let mut video_buffer = GameScreenBuffer::from_vec(vec![0; 16], 4, 4);
+    // SAFETY: the library was built from this source, so it agrees on the
+    // layouts of the types passed to it.
+    unsafe {
+        (library.code().update_and_render)(memory, &input, &mut video_buffer)
+    };
+    memory.state().x_offset
+}
+
+#[test]
+fn touching_the_library_reloads_it() {
+    // A copy of its own, so touching it leaves the build alone.
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("hot-reload");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("game.{}.so", std::process::id()));
+    std::fs::copy(built_library(), &path).unwrap();
+
+    let mut library = GameLibrary::load(&path).unwrap();
+    assert!(!library.reload_if_changed().unwrap());
+    assert_eq!(library.reloads(), 0);
+    let mut memory = GameMemory::allocate(1 << 16, 0);
+    assert_eq!(run_frame(&library, &mut memory), 4);
+
+    let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(modified + Duration::from_secs(1))
+        .unwrap();
+    assert!(library.reload_if_changed().unwrap());
+    assert_eq!(library.reloads(), 1);
+    assert!(!library.reload_if_changed().unwrap());
+    // The reloaded game carries on from the same memory.
+    assert_eq!(run_frame(&library, &mut memory), 8);
+
+    drop(library);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,67 @@
+use handmade_hero::input::{GameButtonState, GameInput};
+
+#[test]
+fn buttons_count_every_half_transition() {
+    let mut button = GameButtonState::default();
+    // Repeating the current state is not a transition.
+    button.process(false);
+    assert_eq!(button, GameButtonState::default());
+    assert!(!button.was_pressed());
+
+    button.process(true);
+    button.process(true);
+    assert_eq!(button.half_transition_count, 1);
+    assert!(button.ended_down);
+    assert!(button.was_pressed());
+
+    // Pressed and released within the frame still counts as a press.
+    button.process(false);
+    assert_eq!(button.half_transition_count, 2);
+    assert!(!button.ended_down);
+    assert!(button.was_pressed());
+
+    // Released without being pressed this frame does not.
+    let mut held = GameButtonState {
+        half_transition_count: 0,
+        ended_down: true,
+    };
+    held.process(false);
+    assert_eq!(held.half_transition_count, 1);
+    assert!(!held.was_pressed());
+}
+
+#[test]
+fn next_frame_keeps_buttons_down_and_clears_transitions() {
+    let mut input = GameInput::default();
+    let keyboard = input.keyboard_mut();
+    keyboard.is_connected = true;
+    keyboard.action_down.process(true);
+    keyboard.move_left.process(true);
+    keyboard.move_left.process(false);
+    let pad = input.gamepad_mut(1);
+    pad.is_analog = true;
+    pad.start.process(true);
+    pad.left_stick.process(0.5, -0.25);
+    pad.left_stick.process(-1.0, 0.75);
+
+    let next = input.next_frame();
+    let keyboard = next.keyboard();
+    assert!(keyboard.is_connected);
+    assert!(keyboard.action_down.ended_down);
+    assert!(!keyboard.move_left.ended_down);
+    for controller in next.controllers.iter() {
+        for button in controller.buttons().iter() {
+            assert_eq!(button.half_transition_count, 0);
+            assert!(!button.was_pressed());
+        }
+    }
+
+    let pad = &next.controllers[2];
+    assert!(pad.is_analog);
+    assert!(pad.start.ended_down);
+    let stick = pad.left_stick;
+    assert_eq!((stick.start_x, stick.start_y), (-1.0, 0.75));
+    assert_eq!((stick.min_x, stick.max_x), (-1.0, -1.0));
+    assert_eq!((stick.min_y, stick.max_y), (0.75, 0.75));
+    assert_eq!((stick.average_x(), stick.average_y()), (-1.0, 0.75));
+}
@@ -0,0 +1,119 @@
+use handmade_hero::wav::LoadedSound;
+use handmade_hero::{ArenaError, GameMemory, MemoryArena, MAX_SOUNDS};
+
+#[test]
+fn pushes_are_aligned_and_do_not_overlap() {
+    let mut storage = [0u8; 256];
+    let arena = MemoryArena::new(&mut storage);
+    let byte = arena.push_struct(1u8).unwrap();
+    let word = arena.push_struct(2u64).unwrap();
+    let bytes = arena.push_bytes(3, 64).unwrap();
+    assert_eq!(word as *mut u64 as usize % 8, 0);
+    assert_eq!(bytes.as_ptr() as usize % 64, 0);
+    bytes.fill(0xff);
+    assert_eq!((*byte, *word), (1, 2));
+}
+
+#[test]
+fn arrays_and_slices() {
+    let mut storage = [0xffu8; 64];
+    let arena = MemoryArena::new(&mut storage);
+    assert_eq!(arena.push_array::<u16>(3).unwrap(), &[0, 0, 0]);
+    assert_eq!(arena.push_slice(&[4u32, 5]).unwrap(), &[4, 5]);
+}
+
+#[test]
+fn overflow_is_an_error() {
+    let mut storage = [0u8; 16];
+    let arena = MemoryArena::new(&mut storage);
+    arena.push_bytes(12, 1).unwrap();
+    assert_eq!(
+        arena.push_array::<u8>(5).unwrap_err(),
+        ArenaError::OutOfMemory {
+            requested: 5,
+            remaining: 4
+        }
+    );
+    assert_eq!(
+        arena.push_array::<u64>(usize::MAX).unwrap_err(),
+        ArenaError::OutOfMemory {
+            requested: usize::MAX,
+            remaining: 4
+        }
+    );
+    assert_eq!(
+        arena.push_bytes(1, 3).unwrap_err(),
+        ArenaError::InvalidAlignment(3)
+    );
+    assert_eq!(arena.used(), 12);
+}
+
+#[test]
+fn temporary_memory_rolls_back() {
+    let mut storage = [0u8; 64];
+    let mut arena = MemoryArena::new(&mut storage);
+    arena.push_bytes(8, 1).unwrap();
+    {
+        let mut temp = arena.begin_temporary_memory();
+        temp.push_bytes(16, 1).unwrap();
+        let nested = temp.begin_temporary_memory();
+        nested.push_bytes(16, 1).unwrap();
+        assert_eq!(nested.used(), 40);
+        nested.end();
+        assert_eq!(temp.used(), 24);
+    }
+    assert_eq!(arena.used(), 8);
+}
+
+#[test]
+fn sub_arenas_take_their_size_from_the_parent() {
+    let mut storage = [0u8; 64];
+    let arena = MemoryArena::new(&mut storage);
+    let sub = arena.sub_arena(32, 16).unwrap();
+    sub.push_bytes(32, 1).unwrap();
+    assert!(sub.push_struct(0u8).is_err());
+    assert_eq!(arena.remaining(), 64 - arena.used());
+    assert!(arena.used() >= 32);
+}
+
+#[test]
+fn game_state_lives_in_permanent_storage() {
+    let mut memory = GameMemory::allocate(1 << 16, 1024);
+    memory.state().x_offset = 7;
+    assert!(memory.is_initialized);
+    assert_eq!(memory.state().x_offset, 7);
+    assert_eq!(memory.transient_arena().size(), 1024);
+}
+
+#[test]
+fn assets_survive_copying_permanent_storage() {
+    let mut memory = GameMemory::allocate(1 << 16, 0);
+    let sound = LoadedSound {
+        samples_per_second: 8000,
+        channels: 2,
+        samples: vec![1, -2, 3, -4].into(),
+    };
+    let id = memory.add_sound(&sound).unwrap();
+    let words = memory.push_asset(&[u64::MAX, 5]).unwrap();
+    assert!(memory.push_asset(&[0u8; 1 << 16]).is_err());
+
+    // The assets must be found through offsets from the copied bytes, the
+    // old block is freed before they are read.
+    let mut copy = GameMemory::allocate(1 << 16, 0);
+    copy.permanent_storage_mut()
+        .copy_from_slice(memory.permanent_storage());
+    copy.is_initialized = true;
+    drop(memory);
+    let stored = copy.state().sounds[id.0 as usize].unwrap();
+    assert_eq!(stored.load(copy.assets()), sound);
+    assert_eq!(copy.assets().get(words), [u64::MAX, 5]);
+
+    // Later assets go after the copied ones.
+    let more = copy.push_asset(&[7u16; 3]).unwrap();
+    assert_eq!(copy.assets().get(words), [u64::MAX, 5]);
+    assert_eq!(copy.assets().get(more), [7; 3]);
+    for _ in 1..MAX_SOUNDS {
+        assert!(copy.add_sound(&sound).is_some());
+    }
+    assert_eq!(copy.add_sound(&sound), None);
+}
@@ -0,0 +1,356 @@
+use std::io::Cursor;
+
+use handmade_hero::envelope::Adsr;
+use handmade_hero::midi::{
+    MidiError, MidiEvent, MidiFile, MidiMessage, MidiPlayer, ProgramMap,
+};
+use handmade_hero::mixer::{Mixer, Voice, MAX_NOTE_EVENTS, MAX_VOICES};
+use handmade_hero::oscillator::{Waveform, Wavetable, WavetableId};
+use handmade_hero::sequencer::Instrument;
+use handmade_hero::wav::{LoadedSound, WavWriter};
+use handmade_hero::{GameAudioBuffer, GameMemory};
+
+const SAMPLES_PER_SECOND: u32 = 48000;
+const TICKS_PER_QUARTER: u16 = 480;
+
+fn variable_length(mut value: u32) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.insert(0, 0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    bytes
+}
+
+fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(body);
+    chunk
+}
+
+// A track of `(delta, bytes)` events, ended for it.
+fn track(events: &[(u32, &[u8])]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (delta, bytes) in events {
+        body.extend(variable_length(*delta));
+        body.extend_from_slice(bytes);
+    }
+    body.extend_from_slice(&[0, 0xff, 0x2f, 0]);
+    chunk(b"MTrk", &body)
+}
+
+fn smf(format: u16, division: u16, tracks: &[Vec<u8>]) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&format.to_be_bytes());
+    header.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+    header.extend_from_slice(&division.to_be_bytes());
+    let mut data = chunk(b"MThd", &header);
+    for track in tracks {
+        data.extend_from_slice(track);
+    }
+    data
+}
+
+fn tempo(microseconds_per_quarter: u32) -> [u8; 6] {
+    let [_, a, b, c] = microseconds_per_quarter.to_be_bytes();
+    [0xff, 0x51, 3, a, b, c]
+}
+
+// 240 BPM for two beats, then 120 BPM. Two notes on channel 1, the second
+// with program 5 and written with running status.
+fn song() -> Vec<u8> {
+    let conductor = track(&[
+        (0, &[0xff, 0x03, 5, b'T', b'e', b'm', b'p', b'o']),
+        (0, &tempo(250_000)),
+        (960, &tempo(500_000)),
+    ]);
+    let notes = track(&[
+        (0, &[0xf0, 3, 0x7e, 0x7f, 0xf7]),
+        (480, &[0x90, 60, 127]),
+        (240, &[60, 0]),
+        (240, &[0xc0, 5]),
+        (480, &[0x90, 64, 64]),
+        (240, &[0x80, 64, 0]),
+    ]);
+    smf(1, TICKS_PER_QUARTER, &[conductor, notes])
+}
+
+#[test]
+fn format_1_files_merge_their_tracks() {
+    let file = MidiFile::from_smf(&song()).unwrap();
+    assert_eq!(file.format, 1);
+    assert_eq!(file.ticks_per_quarter, TICKS_PER_QUARTER);
+    let event = |tick, message| MidiEvent { tick, message };
+    assert_eq!(
+        file.events,
+        [
+            event(0, MidiMessage::Tempo(250_000)),
+            event(
+                480,
+                MidiMessage::NoteOn {
+                    channel: 0,
+                    key: 60,
+                    velocity: 127
+                }
+            ),
+            event(
+                720,
+                MidiMessage::NoteOff {
+                    channel: 0,
+                    key: 60
+                }
+            ),
+            event(960, MidiMessage::Tempo(500_000)),
+            event(
+                960,
+                MidiMessage::ProgramChange {
+                    channel: 0,
+                    program: 5
+                }
+            ),
+            event(
+                1440,
+                MidiMessage::NoteOn {
+                    channel: 0,
+                    key: 64,
+                    velocity: 64
+                }
+            ),
+            event(
+                1680,
+                MidiMessage::NoteOff {
+                    channel: 0,
+                    key: 64
+                }
+            ),
+        ]
+    );
+
+    let single = smf(0, 96, &[track(&[(0, &[0x99, 36, 100])])]);
+    let file = MidiFile::from_smf(&single).unwrap();
+    assert_eq!(file.format, 0);
+    assert_eq!(file.events.len(), 1);
+}
+
+#[test]
+fn malformed_files_are_rejected() {
+    let parse = |data: &[u8]| MidiFile::from_smf(data).unwrap_err();
+    assert_eq!(parse(b"RIFF...."), MidiError::NotAMidiFile);
+    assert_eq!(parse(&smf(2, 96, &[])), MidiError::UnsupportedFormat(2));
+    assert_eq!(
+        parse(&smf(1, 0xe728, &[])),
+        MidiError::UnsupportedDivision(0xe728)
+    );
+    let data = song();
+    assert_eq!(parse(&data[..data.len() - 3]), MidiError::Truncated);
+    assert_eq!(
+        parse(&smf(0, 96, &[track(&[(0, &[60, 100])])])),
+        MidiError::MissingStatus
+    );
+    assert_eq!(
+        parse(&smf(0, 96, &[chunk(b"MTrk", &[0xff, 0xff, 0xff, 0xff, 0])])),
+        MidiError::InvalidVariableLength
+    );
+    // Running status doesn't carry over meta events.
+    assert_eq!(
+        parse(&smf(
+            0,
+            96,
+            &[track(&[
+                (0, &[0x90, 60, 100]),
+                (0, &tempo(1)),
+                (0, &[60, 0])
+            ])]
+        )),
+        MidiError::MissingStatus
+    );
+}
+
+// Plays a constant 1.0 at `volume` without attack or release.
+fn flat(volume: f32) -> Instrument {
+    Instrument::Synth {
+        waveform: Waveform::Wavetable(WavetableId(0)),
+        adsr: Adsr::new(0.0, 0.0, 1.0, 0.0),
+        volume,
+    }
+}
+
+fn load(data: &[u8], programs: ProgramMap) -> (GameMemory, MidiPlayer) {
+    let mut memory = GameMemory::allocate(1 << 16, 0);
+    let file = MidiFile::from_smf(data)
+        .unwrap()
+        .store(&mut memory)
+        .unwrap();
+    (memory, MidiPlayer::new(file, programs))
+}
+
+// Schedules and mixes the next `frame_count` frames.
+fn play(
+    memory: &GameMemory,
+    player: &mut MidiPlayer,
+    mixer: &mut Mixer,
+    frame_count: usize,
+) -> Vec<i16> {
+    let mut samples = vec![0; 2 * frame_count];
+    player.render(memory.assets(), mixer, frame_count, SAMPLES_PER_SECOND);
+    mixer.mix(
+        &mut GameAudioBuffer::new(SAMPLES_PER_SECOND, &mut samples),
+        &[],
+        &[Wavetable::new(&[1.0, 1.0])],
+    );
+    samples
+}
+
+// `(delta, bytes)` pairs borrowed as `track` takes them.
+fn borrowed(events: &[(u32, Vec<u8>)]) -> Vec<(u32, &[u8])> {
+    events
+        .iter()
+        .map(|(delta, bytes)| (*delta, bytes.as_slice()))
+        .collect()
+}
+
+fn render_wav(data: &[u8], frame_counts: &[usize]) -> Vec<u8> {
+    let mut programs = ProgramMap::default();
+    programs.fallback = Some(flat(0.5));
+    programs.set(5, flat(0.25));
+    let (memory, mut player) = load(data, programs);
+    let mut mixer = Mixer::default();
+    let mut wav =
+        WavWriter::new(Cursor::new(Vec::new()), SAMPLES_PER_SECOND, 2).unwrap();
+    for &count in frame_counts {
+        let samples = play(&memory, &mut player, &mut mixer, count);
+        wav.write_samples(&samples).unwrap();
+    }
+    assert!(!player.is_playing());
+    wav.finish().unwrap().into_inner()
+}
+
+#[test]
+fn playback_renders_the_same_wav_every_time() {
+    let whole = render_wav(&song(), &[72000]);
+    assert_eq!(render_wav(&song(), &[1, 11999, 700, 300, 59000]), whole);
+    assert_eq!(render_wav(&song(), &[72000]), whole);
+
+    let sound = LoadedSound::from_wav(&whole).unwrap();
+    let left: Vec<i16> = sound.samples.iter().step_by(2).copied().collect();
+    let sounding = |range: std::ops::Range<usize>| {
+        let level = left[range.start];
+        assert!(left[range.clone()].iter().all(|&sample| sample == level));
+        level
+    };
+    // The first note plays for half a beat at 240 BPM from the second beat.
+    assert_eq!(sounding(0..12000), 0);
+    let loud = sounding(12000..18000);
+    assert_eq!(sounding(18000..48000), 0);
+    // The tempo halves on the third beat, the second note plays on the
+    // fourth with program 5 at half the volume and velocity.
+    let quiet = sounding(48000..60000);
+    assert_eq!(sounding(60000..72000), 0);
+    let ratio = loud as f32 / quiet as f32;
+    assert!((ratio - 2.0 * 127.0 / 64.0).abs() < 0.01, "{}", ratio);
+}
+
+#[test]
+fn general_midi_maps_every_program() {
+    let programs = ProgramMap::general_midi();
+    for program in 0..128 {
+        assert!(programs.instrument(0, program).is_some());
+    }
+    assert_eq!(programs.instrument(9, 0), programs.percussion.as_ref());
+    assert_ne!(programs.instrument(9, 0), programs.instrument(0, 0));
+
+    let mut programs = ProgramMap::default();
+    assert_eq!(programs.instrument(0, 3), None);
+    programs.fallback = Some(flat(1.0));
+    assert_eq!(programs.instrument(0, 3), Some(&flat(1.0)));
+}
+
+#[test]
+fn chords_larger_than_the_mixer_keep_playing() {
+    // Two chords of 20 notes, half a beat each, a beat apart.
+    let mut events: Vec<(u32, Vec<u8>)> = Vec::new();
+    for chord in 0..2 {
+        for key in 40..60 {
+            let delta = if key == 40 && chord == 1 { 240 } else { 0 };
+            events.push((delta, vec![0x90, key, 100]));
+        }
+        for key in 40..60 {
+            events.push((if key == 40 { 240 } else { 0 }, vec![0x80, key, 0]));
+        }
+    }
+    let data = smf(0, TICKS_PER_QUARTER, &[track(&borrowed(&events))]);
+
+    let mut programs = ProgramMap::default();
+    programs.fallback = Some(flat(0.01));
+    let (memory, mut player) = load(&data, programs);
+    let mut mixer = Mixer::default();
+    // 120 BPM, a beat every 24000 frames.
+    let mut active = Vec::new();
+    for _ in 0..8 {
+        play(&memory, &mut player, &mut mixer, 6000);
+        active.push(mixer.active_voices());
+    }
+    assert_eq!(
+        active,
+        [MAX_VOICES, MAX_VOICES, 0, 0, MAX_VOICES, MAX_VOICES, 0, 0]
+    );
+    assert!(!player.is_playing());
+}
+
+#[test]
+fn chords_larger_than_the_note_queue_drop_what_does_not_fit() {
+    // Six notes at once, released half a beat later.
+    let mut chord: Vec<(u32, Vec<u8>)> = Vec::new();
+    for key in 60..66 {
+        chord.push((0, vec![0x90, key, 100]));
+    }
+    for key in 60..66 {
+        chord.push((if key == 60 { 240 } else { 0 }, vec![0x80, key, 0]));
+    }
+    let data = smf(0, TICKS_PER_QUARTER, &[track(&borrowed(&chord))]);
+
+    let mut programs = ProgramMap::default();
+    programs.fallback = Some(flat(0.01));
+    let (memory, mut player) = load(&data, programs);
+    let mut mixer = Mixer::default();
+    // A silent voice leaves room for three note events.
+    let filler = mixer.play(Voice::tone(100.0, 0.0, 0.0)).unwrap();
+    for frame in 3..MAX_NOTE_EVENTS as u32 {
+        assert!(mixer.note_off(filler, frame));
+    }
+    player.render(memory.assets(), &mut mixer, 6000, SAMPLES_PER_SECOND);
+    // The voices of the other three notes are stopped, not left waiting.
+    assert_eq!(mixer.active_voices(), 1 + 3);
+
+    // The notes that did get on are released as usual.
+    let mut active = Vec::new();
+    // Half a beat is 12000 frames, and the mixer is a chunk behind.
+    for _ in 0..2 {
+        play(&memory, &mut player, &mut mixer, 6000);
+        active.push(mixer.active_voices());
+    }
+    assert_eq!(active, [1 + 3, 1]);
+    assert!(!player.is_playing());
+}
+
+#[test]
+fn times_past_64_bits_end_the_song() {
+    // The slowest tempo, then a note more than 2^40 ticks in, where its time
+    // in microseconds times ticks per quarter no longer fits in a u64.
+    let mut far: Vec<(u32, Vec<u8>)> = vec![(0, tempo(0xff_ffff).to_vec())];
+    for _ in 0..4100 {
+        far.push((0x0fff_ffff, vec![0xff, 0x01, 0]));
+    }
+    far.push((0, vec![0x90, 60, 100]));
+    let data = smf(0, TICKS_PER_QUARTER, &[track(&borrowed(&far))]);
+
+    let mut programs = ProgramMap::default();
+    programs.fallback = Some(flat(0.01));
+    let (memory, mut player) = load(&data, programs);
+    let mut mixer = Mixer::default();
+    play(&memory, &mut player, &mut mixer, 6000);
+    assert!(!player.is_playing());
+    assert_eq!(mixer.active_voices(), 0);
+}
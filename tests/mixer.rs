@@ -0,0 +1,130 @@
+use handmade_hero::mixer::{Mixer, SoundId, Voice};
+use handmade_hero::wav::LoadedSound;
+use handmade_hero::GameAudioBuffer;
+
+const SAMPLES_PER_SECOND: u32 = 48000;
+
+fn mix(
+    mixer: &mut Mixer,
+    sounds: &[LoadedSound],
+    frame_count: usize,
+) -> Vec<i16> {
+    let mut samples = vec![0; 2 * frame_count];
+    mixer.mix(
+        &mut GameAudioBuffer::new(SAMPLES_PER_SECOND, &mut samples),
+        sounds,
+        &[],
+    );
+    samples
+}
+
+// A mono sound holding `value` for a second.
+fn constant(value: i16) -> LoadedSound<'static> {
+    LoadedSound {
+        samples_per_second: SAMPLES_PER_SECOND,
+        channels: 1,
+        samples: vec![value; SAMPLES_PER_SECOND as usize].into(),
+    }
+}
+
+#[test]
+fn tones_carry_on_across_mixes_of_any_size() {
+    let render = |frame_counts: &[usize]| {
+        let mut mixer = Mixer::default();
+        mixer.play(Voice::tone(440.0, 0.5, 0.0)).unwrap();
+        let mut samples = Vec::new();
+        for &count in frame_counts {
+            samples.extend(mix(&mut mixer, &[], count));
+        }
+        samples
+    };
+    let whole = render(&[4800]);
+    assert!(whole.iter().any(|&sample| sample != 0));
+    assert_eq!(render(&[1, 255, 256, 257, 0, 1031, 3000]), whole);
+}
+
+#[test]
+fn frequency_changes_keep_the_phase() {
+    let mut mixer = Mixer::default();
+    let tone = mixer.play(Voice::tone(440.0, 1.0, -1.0)).unwrap();
+    let before = mix(&mut mixer, &[], 1000);
+    mixer.voice_mut(tone).unwrap().frequency = 660.0;
+    let after = mix(&mut mixer, &[], 1000);
+
+    // No step between the mixes bigger than a sine at the new frequency can
+    // take within a sample.
+    let largest_step = i16::MAX as f32 * core::f32::consts::TAU * 660.0
+        / SAMPLES_PER_SECOND as f32;
+    let step = (after[0] as f32 - before[before.len() - 2] as f32).abs();
+    assert!(step <= largest_step, "{} > {}", step, largest_step);
+    // A restarted sine would begin at zero.
+    assert!(after[0].abs() > 1000, "{}", after[0]);
+}
+
+#[test]
+fn gain_changes_ramp_across_the_next_mix() {
+    let sounds = [constant(i16::MAX)];
+    let mut mixer = Mixer::default();
+    let voice = mixer
+        .play(Voice::looping_sound(SoundId(0), 0.25, -1.0))
+        .unwrap();
+    let left = |samples: Vec<i16>| -> Vec<i16> {
+        samples.into_iter().step_by(2).collect()
+    };
+    assert!(left(mix(&mut mixer, &sounds, 100))
+        .iter()
+        .all(|&sample| sample == i16::MAX / 4));
+
+    mixer.voice_mut(voice).unwrap().volume = 0.75;
+    let ramp = left(mix(&mut mixer, &sounds, 100));
+    assert!(ramp.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(ramp[0] > i16::MAX / 4 && ramp[0] < i16::MAX / 4 + 200);
+    assert!((ramp[99] - i16::MAX / 4 * 3).abs() <= 1, "{}", ramp[99]);
+    assert!(left(mix(&mut mixer, &sounds, 100))
+        .iter()
+        .all(|&sample| (sample - i16::MAX / 4 * 3).abs() <= 1));
+
+    // Stopping fades out over one mix and frees the voice.
+    mixer.stop(voice);
+    assert!(!mixer.is_playing(voice));
+    let fade = left(mix(&mut mixer, &sounds, 100));
+    assert!(fade.windows(2).all(|pair| pair[0] > pair[1]));
+    assert_eq!(fade[99], 0);
+    assert_eq!(mixer.active_voices(), 0);
+    assert!(mix(&mut mixer, &sounds, 100)
+        .iter()
+        .all(|&sample| sample == 0));
+}
+
+#[test]
+fn voices_add_up_and_clip_at_full_scale() {
+    let sounds = [constant(12000), constant(-30000)];
+    let mut mixer = Mixer::default();
+    let play = |mixer: &mut Mixer, sound, pan| {
+        mixer.play(Voice::sound(SoundId(sound), 1.0, pan)).unwrap()
+    };
+    // Two voices on the left add up, three on the right clip.
+    play(&mut mixer, 0, -1.0);
+    play(&mut mixer, 0, -1.0);
+    play(&mut mixer, 0, 1.0);
+    play(&mut mixer, 0, 1.0);
+    play(&mut mixer, 0, 1.0);
+    let samples = mix(&mut mixer, &sounds, 10);
+    for frame in samples.chunks(2) {
+        assert!((frame[0] - 24000).abs() <= 2, "{}", frame[0]);
+        assert_eq!(frame[1], i16::MAX);
+    }
+
+    let mut mixer = Mixer::default();
+    play(&mut mixer, 1, 0.0);
+    play(&mut mixer, 1, 0.0);
+    let samples = mix(&mut mixer, &sounds, 10);
+    assert!(samples.iter().all(|&sample| sample == i16::MIN));
+
+    mixer.master_volume = 0.25;
+    let samples = mix(&mut mixer, &sounds, 10);
+    let expected = -30000.0 * 2.0 * core::f32::consts::FRAC_1_SQRT_2 * 0.25;
+    assert!(samples
+        .iter()
+        .all(|&sample| (sample as f32 - expected).abs() <= 2.0));
+}
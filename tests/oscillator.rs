@@ -0,0 +1,245 @@
+use handmade_hero::mixer::{Mixer, Voice};
+use handmade_hero::oscillator::{Oscillator, Waveform, Wavetable, WavetableId};
+use handmade_hero::{GameAudioBuffer, GameMemory, MAX_WAVETABLES};
+
+const SAMPLES_PER_SECOND: f32 = 48000.0;
+// A power of two so `BIN_HZ` is exact and a whole number of cycles fits.
+const LEN: usize = 1024;
+const BIN_HZ: f32 = SAMPLES_PER_SECOND / LEN as f32;
+
+fn render(waveform: Waveform, frequency: f32, len: usize) -> Vec<f32> {
+    let mut oscillator = Oscillator::new(waveform);
+    (0..len)
+        .map(|_| oscillator.next_sample(frequency, SAMPLES_PER_SECOND, &[]))
+        .collect()
+}
+
+/// Power of every DFT bin up to Nyquist.
+fn spectrum(samples: &[f32]) -> Vec<f64> {
+    let len = samples.len();
+    (0..=len / 2)
+        .map(|k| {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (n, &x) in samples.iter().enumerate() {
+                let angle =
+                    core::f64::consts::TAU * (k * n % len) as f64 / len as f64;
+                re += x as f64 * angle.cos();
+                im -= x as f64 * angle.sin();
+            }
+            re * re + im * im
+        })
+        .collect()
+}
+
+/// Share of the power that is not at a harmonic of bin `fundamental`.
+fn aliased_power(samples: &[f32], fundamental: usize) -> f64 {
+    let spectrum = spectrum(samples);
+    let total: f64 = spectrum[1..].iter().sum();
+    let aliased: f64 = (1..spectrum.len())
+        .filter(|bin| bin % fundamental != 0)
+        .map(|bin| spectrum[bin])
+        .sum();
+    aliased / total
+}
+
+fn naive(waveform: Waveform, frequency: f32, len: usize) -> Vec<f32> {
+    let step = frequency / SAMPLES_PER_SECOND;
+    (0..len)
+        .map(|n| {
+            let t = (n as f32 * step).fract();
+            match waveform {
+                Waveform::Saw => 2.0 * t - 1.0,
+                Waveform::Square { pulse_width } if t < pulse_width => 1.0,
+                Waveform::Square { .. } => -1.0,
+                Waveform::Triangle => 1.0 - 4.0 * (t - 0.5).abs(),
+                _ => unreachable!(),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn polyblep_waveforms_alias_less_than_naive_ones() {
+    // High enough that many harmonics fold back below Nyquist.
+    let fundamental = 93;
+    let frequency = fundamental as f32 * BIN_HZ;
+    for &waveform in &[
+        Waveform::Saw,
+        Waveform::Square { pulse_width: 0.5 },
+        Waveform::Square { pulse_width: 0.25 },
+        Waveform::Triangle,
+    ] {
+        let band_limited =
+            aliased_power(&render(waveform, frequency, LEN), fundamental);
+        let naive =
+            aliased_power(&naive(waveform, frequency, LEN), fundamental);
+        assert!(
+            band_limited < naive / 4.0,
+            "{:?}: {} of the power aliased, {} naively",
+            waveform,
+            band_limited,
+            naive
+        );
+    }
+}
+
+#[test]
+fn frequencies_are_in_hz_at_any_sample_rate() {
+    for &samples_per_second in &[22050.0, 44100.0, 48000.0] {
+        for &waveform in &[
+            Waveform::Sine,
+            Waveform::Saw,
+            Waveform::Square { pulse_width: 0.5 },
+            Waveform::Triangle,
+        ] {
+            let mut oscillator = Oscillator::new(waveform);
+            let mut cycles = 0;
+            for _ in 0..samples_per_second as usize {
+                let before = oscillator.phase();
+                oscillator.next_sample(440.5, samples_per_second, &[]);
+                if oscillator.phase() < before {
+                    cycles += 1;
+                }
+            }
+            // Half a cycle is left over at the end of the second.
+            assert_eq!(cycles, 440, "{:?} at {}", waveform, samples_per_second);
+        }
+    }
+}
+
+#[test]
+fn pulse_width_sets_the_duty_cycle() {
+    for &pulse_width in &[0.1, 0.25, 0.5, 0.8] {
+        let samples = render(Waveform::Square { pulse_width }, 100.0, 48000);
+        let high = samples.iter().filter(|&&x| x > 0.0).count();
+        let duty = high as f32 / samples.len() as f32;
+        assert!(
+            (duty - pulse_width).abs() < 0.01,
+            "{} {}",
+            pulse_width,
+            duty
+        );
+    }
+}
+
+#[test]
+fn noise_is_deterministic_and_pink_noise_is_darker() {
+    let white = render(Waveform::WhiteNoise, 0.0, 48000);
+    assert_eq!(white, render(Waveform::WhiteNoise, 0.0, 48000));
+    assert!(white.iter().all(|x| (-1.0..1.0).contains(x)));
+    let mean = white.iter().sum::<f32>() / white.len() as f32;
+    assert!(mean.abs() < 0.02, "{}", mean);
+
+    let mut seeded = Oscillator::new(Waveform::WhiteNoise).with_seed(7);
+    let other: Vec<f32> = (0..16)
+        .map(|_| seeded.next_sample(0.0, SAMPLES_PER_SECOND, &[]))
+        .collect();
+    assert_ne!(other, white[..16]);
+
+    // Differencing is a high pass, it keeps about twice the power of white
+    // noise but much less of pink noise.
+    let pink = render(Waveform::PinkNoise, 0.0, 48000);
+    let high_pass_ratio = |samples: &[f32]| {
+        let power: f32 = samples.iter().map(|x| x * x).sum();
+        let difference: f32 =
+            samples.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum();
+        difference / power
+    };
+    assert!((high_pass_ratio(&white) - 2.0).abs() < 0.1);
+    assert!(high_pass_ratio(&pink) < 0.5, "{}", high_pass_ratio(&pink));
+    assert!(pink.iter().all(|x| (-1.0..=1.0).contains(x)));
+}
+
+#[test]
+fn wavetables_drop_harmonics_above_nyquist() {
+    let cycle: Vec<f32> =
+        (0..256).map(|n| 2.0 * n as f32 / 256.0 - 1.0).collect();
+    let wavetables = [Wavetable::new(&cycle)];
+    let mut oscillator = Oscillator::new(Waveform::Wavetable(WavetableId(0)));
+    let fundamental = 93;
+    let samples: Vec<f32> = (0..LEN)
+        .map(|_| {
+            oscillator.next_sample(
+                fundamental as f32 * BIN_HZ,
+                SAMPLES_PER_SECOND,
+                &wavetables,
+            )
+        })
+        .collect();
+    assert!(aliased_power(&samples, fundamental) < 1e-3);
+    let saw = naive(Waveform::Saw, fundamental as f32 * BIN_HZ, LEN);
+    assert!(aliased_power(&saw, fundamental) > 1e-2);
+
+    // At low pitches every harmonic is kept and the cycle plays back as is.
+    let slow = render_table(&wavetables, 48000.0 / 256.0, 256);
+    for (played, expected) in slow.iter().zip(cycle.iter()).skip(8).take(240) {
+        assert!((played - expected).abs() < 0.05, "{} {}", played, expected);
+    }
+    assert_eq!(render_table(&[], 440.0, 16), vec![0.0; 16]);
+}
+
+#[test]
+fn wavetables_play_the_same_from_game_memory() {
+    let cycle: Vec<f32> =
+        (0..64).map(|n| ((n * 7) % 13) as f32 / 6.0 - 1.0).collect();
+    let table = Wavetable::new(&cycle);
+    let mut memory = GameMemory::allocate(1 << 16, 0);
+    let id = memory.add_wavetable(&table).unwrap();
+    let stored = memory.state().wavetables[id.0 as usize].unwrap();
+    let loaded = [stored.load(memory.assets())];
+    assert_eq!(loaded[0], table);
+    for frequency in [50.0, 1000.0, 9000.0] {
+        assert_eq!(
+            render_table(&loaded, frequency, 256),
+            render_table(std::slice::from_ref(&table), frequency, 256)
+        );
+    }
+    // Empty slots play silence.
+    assert_eq!(
+        render_table(&[Wavetable::default()], 440.0, 16),
+        vec![0.0; 16]
+    );
+
+    for _ in 1..MAX_WAVETABLES {
+        assert!(memory.add_wavetable(&table).is_some());
+    }
+    assert_eq!(memory.add_wavetable(&table), None);
+}
+
+fn render_table(
+    wavetables: &[Wavetable],
+    frequency: f32,
+    len: usize,
+) -> Vec<f32> {
+    let mut oscillator = Oscillator::new(Waveform::Wavetable(WavetableId(0)));
+    (0..len)
+        .map(|_| {
+            oscillator.next_sample(frequency, SAMPLES_PER_SECOND, wavetables)
+        })
+        .collect()
+}
+
+#[test]
+fn the_mixer_plays_oscillator_voices() {
+    let mut mixer = Mixer::default();
+    let wavetables = [Wavetable::new(&[0.0, 1.0, 0.0, -1.0])];
+    mixer
+        .play(Voice::oscillator(Waveform::Saw, 220.0, 0.5, 0.0))
+        .unwrap();
+    let table = mixer
+        .play(Voice::oscillator(
+            Waveform::Wavetable(WavetableId(0)),
+            330.0,
+            0.5,
+            0.0,
+        ))
+        .unwrap();
+    let mut samples = vec![0i16; 2 * 480];
+    mixer.mix(
+        &mut GameAudioBuffer::new(48000, &mut samples),
+        &[],
+        &wavetables,
+    );
+    assert!(samples.iter().any(|&sample| sample != 0));
+    assert!(mixer.is_playing(table));
+}
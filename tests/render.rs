@@ -0,0 +1,144 @@
+use handmade_hero::render::{
+    draw_circle, draw_line, draw_rect, fill_circle, fill_rect, fill_triangle,
+};
+use handmade_hero::GameScreenBuffer;
+
+const WIDTH: i32 = 8;
+const HEIGHT: i32 = 6;
+
+// The buffer's rows padded by two pixels each, so writes past the end of a
+// row show up in the padding.
+fn padded_frame() -> GameScreenBuffer {
+    let stride = WIDTH as usize + 2;
+    GameScreenBuffer::from_vec_with_pitch(
+        vec![0; stride * HEIGHT as usize],
+        WIDTH,
+        HEIGHT,
+        stride * 4,
+    )
+}
+
+// The pixels set to `color`, by row.
+fn filled(frame: &GameScreenBuffer, color: u32) -> Vec<Vec<i32>> {
+    (0..HEIGHT)
+        .map(|y| {
+            (0..WIDTH)
+                .filter(|&x| frame.get_pixel(x, y) == Some(color))
+                .collect()
+        })
+        .collect()
+}
+
+fn padding_untouched(frame: &GameScreenBuffer) -> bool {
+    frame
+        .pixels()
+        .chunks(frame.stride())
+        .all(|row| row[WIDTH as usize..].iter().all(|&pixel| pixel == 0))
+}
+
+#[test]
+fn rectangles_are_clipped_to_every_edge() {
+    let mut frame = padded_frame();
+    fill_rect(&mut frame, -3, -2, 2, 1, 1);
+    fill_rect(&mut frame, 6, 4, 20, 9, 2);
+    fill_rect(&mut frame, -100, 3, 100, 4, 3);
+    assert_eq!(
+        filled(&frame, 1),
+        [vec![0, 1], vec![], vec![], vec![], vec![], vec![]]
+    );
+    assert_eq!(
+        filled(&frame, 2),
+        [vec![], vec![], vec![], vec![], vec![6, 7], vec![6, 7]]
+    );
+    assert_eq!(filled(&frame, 3)[3], (0..WIDTH).collect::<Vec<_>>());
+    assert!(padding_untouched(&frame));
+}
+
+#[test]
+fn rectangles_outside_or_empty_draw_nothing() {
+    let mut frame = padded_frame();
+    fill_rect(&mut frame, WIDTH, 0, WIDTH + 4, HEIGHT, 1);
+    fill_rect(&mut frame, 0, -4, WIDTH, 0, 1);
+    fill_rect(&mut frame, 2, 2, 2, 5, 1);
+    fill_rect(&mut frame, 5, 3, 2, 1, 1);
+    fill_rect(&mut frame, i32::MIN, i32::MIN, i32::MAX, -1, 1);
+    draw_rect(&mut frame, 4, 4, 4, 4, 1);
+    assert!(frame.pixels().iter().all(|&pixel| pixel == 0));
+}
+
+#[test]
+fn outlines_lose_the_edges_outside_the_buffer() {
+    let mut frame = padded_frame();
+    draw_rect(&mut frame, -2, 1, 4, 4, 1);
+    assert_eq!(
+        filled(&frame, 1),
+        [
+            vec![],
+            vec![0, 1, 2, 3],
+            vec![3],
+            vec![0, 1, 2, 3],
+            vec![],
+            vec![]
+        ]
+    );
+
+    let mut frame = padded_frame();
+    draw_rect(&mut frame, 5, -1, WIDTH + 1, HEIGHT + 1, 1);
+    let left_edge_only = (0..HEIGHT).map(|_| vec![5]).collect::<Vec<_>>();
+    assert_eq!(filled(&frame, 1), left_edge_only);
+    assert!(padding_untouched(&frame));
+}
+
+fn blank(frame: &GameScreenBuffer) -> bool {
+    frame.pixels().iter().all(|&pixel| pixel == 0)
+}
+
+fn covered(frame: &GameScreenBuffer, color: u32) -> bool {
+    filled(frame, color)
+        .iter()
+        .all(|row| row.len() == WIDTH as usize)
+}
+
+#[test]
+fn shapes_at_extreme_coordinates_neither_overflow_nor_hang() {
+    let (min, max) = (i32::MIN, i32::MAX);
+
+    let mut frame = padded_frame();
+    // Two halves of the largest square, meeting along the diagonal.
+    fill_triangle(&mut frame, (min, min), (max, min), (max, max), 1);
+    fill_triangle(&mut frame, (min, min), (max, max), (min, max), 1);
+    assert!(covered(&frame, 1));
+    fill_triangle(&mut frame, (max, max), (max, max - 1), (max - 1, max), 2);
+    fill_triangle(&mut frame, (min, 0), (min + 1, 0), (min, 5), 2);
+    assert!(filled(&frame, 2).iter().all(Vec::is_empty));
+    assert!(padding_untouched(&frame));
+
+    let mut frame = padded_frame();
+    fill_circle(&mut frame, 3, 2, max, 1);
+    assert!(covered(&frame, 1));
+    // The circle's edge runs by the buffer's corner, the rest is too far.
+    fill_circle(&mut frame, max, max, max, 2);
+    fill_circle(&mut frame, min, min, max, 2);
+    fill_circle(&mut frame, min, 3, 0, 2);
+    assert!(filled(&frame, 2).iter().all(Vec::is_empty));
+    assert!(padding_untouched(&frame));
+
+    let mut frame = padded_frame();
+    // The outline surrounds the buffer, or passes just by it.
+    draw_circle(&mut frame, 3, 2, max, 1);
+    draw_circle(&mut frame, min, 3, max, 1);
+    draw_circle(&mut frame, max, min, max, 1);
+    assert!(blank(&frame));
+    // Its edge down the first column.
+    draw_circle(&mut frame, min + 1, 3, max, 1);
+    assert!(filled(&frame, 1).iter().all(|row| row == &[0]));
+    assert!(padding_untouched(&frame));
+
+    let mut frame = padded_frame();
+    draw_line(&mut frame, min, min, max, max, 1);
+    draw_line(&mut frame, min, 3, max, 3, 2);
+    assert_eq!(filled(&frame, 2)[3], (0..WIDTH).collect::<Vec<_>>());
+    draw_line(&mut frame, max, min, max, max, 3);
+    assert!(filled(&frame, 3).iter().all(Vec::is_empty));
+    assert!(padding_untouched(&frame));
+}
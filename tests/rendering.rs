@@ -0,0 +1,176 @@
+use handmade_hero::audio_ring::{
+    AudioRingBuffer, AudioSyncMarker, AudioSyncMarkers, SimulatedAudioDevice,
+};
+use handmade_hero::bitmap::{draw_bitmap, LoadedBitmap};
+use handmade_hero::golden::{self, GoldenError, GoldenImages};
+use handmade_hero::render::*;
+use handmade_hero::timing::FakeClock;
+use handmade_hero::{GameInput, GameMemory, GameScreenBuffer};
+
+const WIDTH: i32 = 64;
+const HEIGHT: i32 = 48;
+
+fn golden_images() -> GoldenImages {
+    GoldenImages::new(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"),
+        concat!(env!("CARGO_TARGET_TMPDIR"), "/golden-failures"),
+        1,
+    )
+}
+
+fn check(name: &str, frame: &GameScreenBuffer) {
+    if let Err(error) = golden_images().check(name, frame) {
+        panic!("{}: {}", name, error);
+    }
+}
+
+fn blank_frame() -> GameScreenBuffer {
+    GameScreenBuffer::from_vec(
+        vec![0; (WIDTH * HEIGHT) as usize],
+        WIDTH,
+        HEIGHT,
+    )
+}
+
+fn render_game_frame(video_buffer: &mut GameScreenBuffer) {
+    let mut memory = GameMemory::allocate(1 << 16, 0);
+    let state = memory.state();
+    state.x_offset = 10;
+    state.y_offset = 20;
+    handmade_hero::game_update_and_render(
+        &mut memory,
+        &GameInput::default(),
+        video_buffer,
+    );
+}
+
+#[test]
+fn game_frame_matches_golden() {
+    let mut frame = blank_frame();
+    render_game_frame(&mut frame);
+    check("game_frame", &frame);
+}
+
+#[test]
+fn padded_rows_render_like_packed_rows() {
+    let stride = WIDTH as usize + 5;
+    let mut frame = GameScreenBuffer::from_vec_with_pitch(
+        vec![0xdead_beef; stride * HEIGHT as usize],
+        WIDTH,
+        HEIGHT,
+        stride * 4,
+    );
+    render_game_frame(&mut frame);
+    check("game_frame", &frame);
+
+    for row in frame.pixels().chunks(stride) {
+        assert!(row[WIDTH as usize..].iter().all(|&p| p == 0xdead_beef));
+    }
+}
+
+#[test]
+fn rasterizer_shapes_match_golden() {
+    let mut frame = blank_frame();
+    fill_rect(&mut frame, -8, -8, 20, 12, rgb(200, 40, 40));
+    draw_rect(&mut frame, 4, 16, 60, 44, rgb(255, 255, 255));
+    draw_line(&mut frame, -10, 47, 80, 0, rgb(40, 200, 40));
+    draw_line(&mut frame, 0, 0, 63, 47, rgb(40, 40, 200));
+    draw_circle(&mut frame, 32, 30, 12, rgb(255, 200, 0));
+    fill_circle(&mut frame, 60, 44, 9, rgb(0, 200, 200));
+    fill_triangle(&mut frame, (30, 2), (62, 10), (40, 24), rgb(200, 0, 200));
+    fill_triangle(&mut frame, (30, 2), (40, 24), (24, 20), rgb(120, 120, 0));
+    check("rasterizer_shapes", &frame);
+}
+
+#[test]
+fn bitmap_blit_matches_golden() {
+    let mut frame = blank_frame();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let checker = if (x / 8 + y / 8) % 2 == 0 { 0x60 } else { 0xa0 };
+            frame.put_pixel(x, y, rgb(checker, checker, checker));
+        }
+    }
+
+    let (width, height) = (24, 16);
+    let pixels = (0..height)
+        .flat_map(|y| {
+            (0..width).map(move |x| {
+                let alpha = (x * 255 / (width - 1)) as u32;
+                alpha << 24 | rgb(255, (y * 16) as u8, 0)
+            })
+        })
+        .collect();
+    let bitmap = LoadedBitmap {
+        width,
+        height,
+        align_x: 0,
+        align_y: 0,
+        pixels,
+    }
+    .with_alignment(width / 2, height);
+
+    draw_bitmap(&mut frame, &bitmap, 32, 30);
+    draw_bitmap(&mut frame, &bitmap, 4, 10);
+    draw_bitmap(&mut frame, &bitmap, 60, 52);
+    check("bitmap_blit", &frame);
+}
+
+#[test]
+fn audio_sync_markers_match_golden() {
+    let mut clock = FakeClock::new(1_000_000);
+    clock.ticks_per_read = 0;
+    let mut ring = AudioRingBuffer::new(960, 4, 960 * 4, 48);
+    let mut device = SimulatedAudioDevice::new(960, 4, 960 * 4, &clock);
+    let mut markers = AudioSyncMarkers::new(6);
+    for &seconds in &[0.033, 0.035, 0.031, 0.120, 0.033, 0.034, 0.033] {
+        let (play_cursor, write_cursor) = device.current_position(&clock);
+        let write = ring.next_write(play_cursor, write_cursor);
+        markers.record(AudioSyncMarker::new(play_cursor, write_cursor, &write));
+        device.write(&ring, &write, &vec![0; write.sample_count as usize * 2]);
+        ring.commit(&write);
+        clock.advance(seconds);
+    }
+
+    let mut frame = GameScreenBuffer::from_vec(vec![0; 96 * 176], 96, 176);
+    markers.draw(&mut frame, ring.buffer_size);
+    check("audio_sync_markers", &frame);
+}
+
+#[test]
+fn ppm_round_trips() {
+    let mut frame = blank_frame();
+    render_game_frame(&mut frame);
+    let decoded = golden::decode_ppm(&golden::encode_ppm(&frame)).unwrap();
+    assert_eq!(decoded.width(), WIDTH);
+    assert_eq!(decoded.height(), HEIGHT);
+    assert_eq!(decoded.pixels(), frame.pixels());
+}
+
+#[test]
+fn mismatches_outside_tolerance_write_a_diff() {
+    let expected = blank_frame();
+    let mut actual = blank_frame();
+    actual.put_pixel(3, 4, rgb(0, 2, 0));
+    actual.put_pixel(5, 6, rgb(0, 0, 3));
+
+    let comparison = golden::compare(&actual, &expected, 2).unwrap();
+    assert_eq!(comparison.mismatched_pixels, 1);
+    assert_eq!(comparison.max_channel_difference, 3);
+    assert_eq!(comparison.diff.get_pixel(5, 6), Some(rgb(255, 0, 0)));
+    assert_eq!(comparison.diff.get_pixel(3, 4), Some(0));
+
+    let dir = concat!(env!("CARGO_TARGET_TMPDIR"), "/golden-self-test");
+    let mut images = GoldenImages::new(dir, dir, 2);
+    images.update = false;
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(images.golden_path("frame"), golden::encode_ppm(&expected))
+        .unwrap();
+    match images.check("frame", &actual) {
+        Err(GoldenError::Mismatch { diff, .. }) => {
+            let diff = golden::decode_ppm(&std::fs::read(diff).unwrap());
+            assert_eq!(diff.unwrap().get_pixel(5, 6), Some(rgb(255, 0, 0)));
+        }
+        _ => panic!("expected a mismatch"),
+    }
+}
@@ -0,0 +1,165 @@
+use handmade_hero::replay::{self, InputLoop, LoopState, ReplayError};
+use handmade_hero::{GameInput, GameMemory, GameScreenBuffer};
+
+fn run_frame(memory: &mut GameMemory, input: &GameInput) {
+    let mut video_buffer = GameScreenBuffer::from_vec(vec![0; 16], 4, 4);
+    handmade_hero::game_update_and_render(memory, input, &mut video_buffer);
+}
+
+fn walking_input(frame: u32) -> GameInput {
+    let mut input = GameInput::default();
+    let keyboard = input.keyboard_mut();
+    keyboard.is_connected = true;
+    keyboard.move_right.process(true);
+    keyboard.move_up.process(frame.is_multiple_of(3));
+    input
+}
+
+#[test]
+fn playback_restores_the_snapshot_each_loop() {
+    let mut memory = GameMemory::allocate(1 << 16, 0);
+    let mut input_loop = InputLoop::default();
+    for frame in 0..4 {
+        run_frame(&mut memory, &walking_input(frame));
+    }
+
+    input_loop.toggle(&mut memory);
+    assert_eq!(input_loop.state(), LoopState::Recording);
+    let start = (memory.state().x_offset, memory.state().y_offset);
+    for frame in 4..10 {
+        let mut input = walking_input(frame);
+        input_loop.process(&mut input, &mut memory);
+        run_frame(&mut memory, &input);
+    }
+    let end = (memory.state().x_offset, memory.state().y_offset);
+
+    input_loop.toggle(&mut memory);
+    assert_eq!(input_loop.state(), LoopState::PlayingBack);
+    assert_eq!(input_loop.recorded_frames(), 6);
+    assert_eq!((memory.state().x_offset, memory.state().y_offset), start);
+    for _ in 0..3 {
+        for _ in 0..6 {
+            // Whatever the platform read is replaced by the recording.
+            let mut input = GameInput::default();
+            input_loop.process(&mut input, &mut memory);
+            run_frame(&mut memory, &input);
+        }
+        assert_eq!((memory.state().x_offset, memory.state().y_offset), end);
+    }
+
+    input_loop.toggle(&mut memory);
+    assert_eq!(input_loop.state(), LoopState::Idle);
+}
+
+#[test]
+fn replay_files_round_trip() {
+    let mut memory = GameMemory::allocate(1 << 16, 0);
+    let mut input_loop = InputLoop::default();
+    input_loop.start_recording(&memory);
+    for frame in 0..20 {
+        let mut input = walking_input(frame);
+        input.gamepad_mut(1).is_analog = true;
+        input.gamepad_mut(1).left_stick.process(0.25, -1.0);
+        input_loop.process(&mut input, &mut memory);
+        run_frame(&mut memory, &input);
+    }
+    let recorded_hash = replay::state_hash(&memory);
+
+    let mut file = Vec::new();
+    input_loop.write_to(&mut file).unwrap();
+    let mut memory = GameMemory::allocate(1 << 16, 0);
+    let mut replayed = InputLoop::read_from(&file[..], &memory).unwrap();
+    assert_eq!(replayed.recorded_frames(), 20);
+
+    replayed.start_playback(&mut memory);
+    for _ in 0..20 {
+        let mut input = GameInput::default();
+        replayed.process(&mut input, &mut memory);
+        run_frame(&mut memory, &input);
+    }
+    assert_eq!(replay::state_hash(&memory), recorded_hash);
+}
+
+#[test]
+fn damaged_replay_files_are_rejected() {
+    let mut memory = GameMemory::allocate(1 << 16, 0);
+    let mut input_loop = InputLoop::default();
+    input_loop.start_recording(&memory);
+    input_loop.process(&mut GameInput::default(), &mut memory);
+    let mut file = Vec::new();
+    input_loop.write_to(&mut file).unwrap();
+
+    assert!(matches!(
+        InputLoop::read_from(&file[..file.len() - 1], &memory),
+        Err(ReplayError::Truncated)
+    ));
+    let mut wrong_build = file.clone();
+    wrong_build[8] ^= 1;
+    assert!(matches!(
+        InputLoop::read_from(&wrong_build[..], &memory),
+        Err(ReplayError::BuildMismatch { .. })
+    ));
+    let mut wrong_version = file.clone();
+    wrong_version[4] = 9;
+    assert!(matches!(
+        InputLoop::read_from(&wrong_version[..], &memory),
+        Err(ReplayError::UnsupportedVersion(9))
+    ));
+    assert!(matches!(
+        InputLoop::read_from(&b"RIFF"[..], &memory),
+        Err(ReplayError::NotAReplay)
+    ));
+
+    // Snapshots bigger than the memory they are for are refused before
+    // anything is read into memory.
+    assert!(matches!(
+        InputLoop::read_from(&file[..], &GameMemory::allocate(1 << 15, 0)),
+        Err(ReplayError::SnapshotTooLarge {
+            size: 65536,
+            permanent_storage_size: 32768,
+        })
+    ));
+    let mut huge = file.clone();
+    huge[16..24].copy_from_slice(&(1u64 << 60).to_le_bytes());
+    assert!(matches!(
+        InputLoop::read_from(&huge[..], &memory),
+        Err(ReplayError::SnapshotTooLarge { .. })
+    ));
+}
+
+#[test]
+fn replays_with_a_damaged_snapshot_are_rejected() {
+    let mut memory = GameMemory::allocate(1 << 16, 0);
+    run_frame(&mut memory, &walking_input(0));
+    let mut input_loop = InputLoop::default();
+    input_loop.start_recording(&memory);
+    input_loop.process(&mut GameInput::default(), &mut memory);
+    let mut file = Vec::new();
+    input_loop.write_to(&mut file).unwrap();
+    assert!(InputLoop::read_from(&file[..], &memory).is_ok());
+
+    // The snapshot starts after the 45 byte header, the checksum is at 32.
+    for &offset in &[45, 32] {
+        let mut damaged = file.clone();
+        damaged[offset] ^= 0x80;
+        assert!(matches!(
+            InputLoop::read_from(&damaged[..], &memory),
+            Err(ReplayError::SnapshotChecksumMismatch { .. })
+        ));
+    }
+}
+
+#[test]
+fn hashing_the_state_does_not_write_it() {
+    let memory = GameMemory::allocate(1 << 16, 0);
+    let unwritten = replay::state_hash(&memory);
+    assert!(!memory.is_initialized);
+
+    let mut written = GameMemory::allocate(1 << 16, 0);
+    written.state();
+    assert_ne!(replay::state_hash(&written), unwritten);
+    written.state().x_offset += 1;
+    let moved = replay::state_hash(&written);
+    written.state().x_offset -= 1;
+    assert_ne!(replay::state_hash(&written), moved);
+}
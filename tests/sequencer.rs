@@ -0,0 +1,258 @@
+use handmade_hero::envelope::Adsr;
+use handmade_hero::mixer::{Mixer, SoundId, Voice, MAX_NOTE_EVENTS};
+use handmade_hero::oscillator::{Waveform, Wavetable, WavetableId};
+use handmade_hero::sequencer::{
+    note_frequency, Cell, Instrument, InstrumentId, Pattern, Sequencer, Song,
+};
+use handmade_hero::wav::LoadedSound;
+use handmade_hero::{GameAudioBuffer, GameMemory};
+
+const SAMPLES_PER_SECOND: u32 = 48000;
+// 120 BPM at 4 rows per beat.
+const FRAMES_PER_ROW: usize = 6000;
+
+// Plays a constant 1.0 without attack or release, so the output is the
+// velocity of whatever note is on.
+fn flat() -> Instrument {
+    Instrument::Synth {
+        waveform: Waveform::Wavetable(WavetableId(0)),
+        adsr: Adsr::new(0.0, 0.0, 1.0, 0.0),
+        volume: 1.0,
+    }
+}
+
+fn note(key: u8, velocity: f32) -> Cell {
+    Cell::Note {
+        key,
+        instrument: InstrumentId(0),
+        velocity,
+    }
+}
+
+fn song(patterns: Vec<Pattern>, order: Vec<usize>) -> Song {
+    Song {
+        bpm: 120.0,
+        rows_per_beat: 4,
+        instruments: vec![flat()],
+        patterns,
+        order,
+        loop_range: None,
+    }
+}
+
+// A sequencer playing a song stored in its own game memory on its own
+// mixer.
+struct Player {
+    memory: GameMemory,
+    sequencer: Sequencer,
+    mixer: Mixer,
+}
+
+impl Player {
+    fn new(song: &Song) -> Self {
+        let mut memory = GameMemory::allocate(1 << 16, 0);
+        let song = song.store(&mut memory).unwrap();
+        Self {
+            memory,
+            sequencer: Sequencer::new(song),
+            mixer: Mixer::default(),
+        }
+    }
+
+    // The left channel of `frame_counts` mixes, panned centre.
+    fn render(
+        &mut self,
+        sounds: &[LoadedSound],
+        frame_counts: &[usize],
+    ) -> Vec<i16> {
+        let wavetables = [Wavetable::new(&[1.0, 1.0])];
+        let mut left = Vec::new();
+        for &count in frame_counts {
+            let mut samples = vec![0; 2 * count];
+            self.sequencer.render(
+                self.memory.assets(),
+                &mut self.mixer,
+                count,
+                SAMPLES_PER_SECOND,
+            );
+            self.mixer.mix(
+                &mut GameAudioBuffer::new(SAMPLES_PER_SECOND, &mut samples),
+                sounds,
+                &wavetables,
+            );
+            left.extend(samples.iter().step_by(2));
+        }
+        left
+    }
+}
+
+// The velocity each row played at, read from the middle of the row.
+fn row_levels(left: &[i16], frames_per_row: usize) -> Vec<f32> {
+    let full = i16::MAX as f32 * core::f32::consts::FRAC_1_SQRT_2;
+    left.chunks(frames_per_row)
+        .map(|row| (row[row.len() / 2] as f32 / full * 100.0).round() / 100.0)
+        .collect()
+}
+
+#[test]
+fn rows_start_on_their_frame_however_the_mix_is_split() {
+    let mut pattern = Pattern::new(4, 2);
+    pattern.set(0, 0, note(60, 0.5));
+    pattern.set(1, 1, note(64, 0.25));
+    pattern.set(2, 0, Cell::Off);
+    pattern.set(3, 0, note(67, 0.1));
+    let whole = Player::new(&song(vec![pattern.clone()], vec![0]))
+        .render(&[], &[5 * FRAMES_PER_ROW]);
+    assert_eq!(
+        row_levels(&whole, FRAMES_PER_ROW),
+        [0.5, 0.75, 0.25, 0.35, 0.0]
+    );
+    for &row in &[1, 2, 3] {
+        let start = row * FRAMES_PER_ROW;
+        assert_ne!(whole[start - 1], whole[start], "row {}", row);
+    }
+    // Both notes are released once the last row has played.
+    assert_eq!(whole[4 * FRAMES_PER_ROW - 1], whole[3 * FRAMES_PER_ROW]);
+    assert_eq!(whole[4 * FRAMES_PER_ROW], 0);
+
+    let mut player = Player::new(&song(vec![pattern], vec![0]));
+    let chunked =
+        player.render(&[], &[1, 5999, 0, 1, 800, 11000, 3, 5197, 6999]);
+    assert_eq!(chunked, whole);
+    assert!(!player.sequencer.is_playing());
+    assert_eq!(player.sequencer.frame(), 5 * FRAMES_PER_ROW as u64);
+}
+
+#[test]
+fn songs_loop_back_to_the_loop_range() {
+    let patterns = (1..=3)
+        .map(|row| {
+            let mut pattern = Pattern::new(2, 1);
+            pattern.set(0, 0, note(60, row as f32 / 10.0));
+            pattern
+        })
+        .collect();
+    let mut song = song(patterns, vec![0, 1, 2, 0]);
+    song.loop_range = Some(1..3);
+    let mut player = Player::new(&song);
+    let left = player.render(&[], &[14 * FRAMES_PER_ROW]);
+    assert_eq!(
+        row_levels(&left, FRAMES_PER_ROW),
+        [
+            0.1, 0.1, 0.2, 0.2, 0.3, 0.3, 0.2, 0.2, 0.3, 0.3, 0.2, 0.2, 0.3,
+            0.3
+        ]
+    );
+    assert!(player.sequencer.is_playing());
+    assert_eq!(player.sequencer.position(), (1, 0));
+}
+
+#[test]
+fn nothing_plays_without_a_sample_rate() {
+    let mut pattern = Pattern::new(1, 1);
+    pattern.set(0, 0, note(60, 0.5));
+    let mut song = song(vec![pattern], vec![0]);
+    song.loop_range = Some(0..1);
+    let mut player = Player::new(&song);
+    // A looping song would otherwise replay its rows forever.
+    player
+        .sequencer
+        .render(player.memory.assets(), &mut player.mixer, 1000, 0);
+    assert_eq!(player.mixer.active_voices(), 0);
+    assert_eq!(player.sequencer.position(), (0, 0));
+    assert_eq!(player.sequencer.frame(), 0);
+
+    let left = player.render(&[], &[FRAMES_PER_ROW]);
+    assert_eq!(row_levels(&left, FRAMES_PER_ROW), [0.5]);
+}
+
+#[test]
+fn notes_that_do_not_fit_the_note_queue_are_dropped() {
+    let mut pattern = Pattern::new(2, 1);
+    pattern.set(0, 0, note(60, 0.5));
+    pattern.set(1, 0, note(60, 0.25));
+    let mut player = Player::new(&song(vec![pattern], vec![0]));
+    // A silent voice takes every note event of the first row.
+    let filler = player.mixer.play(Voice::tone(100.0, 0.0, 0.0)).unwrap();
+    for frame in 0..MAX_NOTE_EVENTS as u32 {
+        assert!(player.mixer.note_off(filler, frame));
+    }
+
+    player.sequencer.render(
+        player.memory.assets(),
+        &mut player.mixer,
+        FRAMES_PER_ROW,
+        SAMPLES_PER_SECOND,
+    );
+    // The voice started for the first row is stopped rather than left
+    // waiting for a note that never comes.
+    assert_eq!(player.mixer.active_voices(), 1);
+    let mut samples = vec![0; 2 * FRAMES_PER_ROW];
+    player.mixer.mix(
+        &mut GameAudioBuffer::new(SAMPLES_PER_SECOND, &mut samples),
+        &[],
+        &[],
+    );
+    assert!(samples.iter().all(|&sample| sample == 0));
+
+    // The queue has room again for the second row.
+    let left = player.render(&[], &[FRAMES_PER_ROW]);
+    assert_eq!(row_levels(&left, FRAMES_PER_ROW), [0.25]);
+    assert_eq!(player.mixer.active_voices(), 2);
+}
+
+#[test]
+fn tempo_changes_take_effect_from_the_next_row() {
+    let mut pattern = Pattern::new(4, 1);
+    for row in 0..4 {
+        pattern.set(row, 0, note(60, if row % 2 == 0 { 0.5 } else { 0.25 }));
+    }
+    let mut player = Player::new(&song(vec![pattern], vec![0]));
+    let mut left = player.render(&[], &[1000]);
+    player.sequencer.set_bpm(240.0);
+    left.extend(player.render(&[], &[12000]));
+    // The row already started still takes 6000 frames, the rest 3000.
+    assert!(left[..6000].iter().all(|&sample| sample == left[0]));
+    assert_eq!(row_levels(&left[..6000], 6000), [0.5]);
+    assert_eq!(row_levels(&left[6000..], 3000), [0.25, 0.5, 0.25]);
+    assert_ne!(left[8999], left[9000]);
+}
+
+#[test]
+fn sample_instruments_are_pitched_from_their_root() {
+    // One cycle of a square wave every 48 frames, 1000Hz at 48kHz.
+    let cycle: Vec<i16> = (0..48)
+        .flat_map(|frame| {
+            let value = if frame < 24 { 16000 } else { -16000 };
+            vec![value, value]
+        })
+        .collect();
+    let sound = LoadedSound {
+        samples_per_second: SAMPLES_PER_SECOND,
+        channels: 2,
+        samples: cycle.repeat(500).into(),
+    };
+    let mut pattern = Pattern::new(1, 1);
+    pattern.set(0, 0, note(69 + 12, 1.0));
+    let mut song = song(vec![pattern], vec![0]);
+    song.instruments = vec![Instrument::Sample {
+        sound: SoundId(0),
+        root_frequency: 440.0,
+        adsr: Adsr::new(0.0, 0.0, 1.0, 0.0),
+        volume: 1.0,
+    }];
+    let left = Player::new(&song).render(&[sound], &[4800]);
+    // An octave up plays twice as fast, 2000Hz.
+    let crossings = left
+        .windows(2)
+        .filter(|pair| (pair[0] > 0) != (pair[1] > 0))
+        .count();
+    assert_eq!(crossings, 2 * 200 - 1);
+}
+
+#[test]
+fn note_frequencies_are_equal_tempered() {
+    assert_eq!(note_frequency(69), 440.0);
+    assert_eq!(note_frequency(81), 880.0);
+    assert!((note_frequency(60) - 261.6256).abs() < 1e-3);
+}
@@ -0,0 +1,98 @@
+use handmade_hero::timing::{Clock, FakeClock, FramePacer, FrameTimer};
+
+const TICKS_PER_SECOND: u64 = 1_000_000;
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 1e-4,
+        "{} is not {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn short_frames_sleep_then_spin_to_the_target() {
+    let mut clock = FakeClock::new(TICKS_PER_SECOND);
+    let mut pacer = FramePacer::new(30.0, &clock);
+    for _ in 0..3 {
+        clock.slept = 0.0;
+        clock.advance(0.010);
+        let wait = pacer.wait_for_frame_end(&mut clock);
+        assert!(!wait.missed);
+        assert_close(wait.work_seconds, 0.010);
+        assert_close(wait.frame_seconds, 1.0 / 30.0);
+        assert_close(clock.slept, 1.0 / 30.0 - 0.010 - pacer.sleep_margin);
+    }
+    assert_eq!(pacer.missed_frames(), 0);
+}
+
+#[test]
+fn late_wakeups_are_not_padded_further() {
+    let mut clock = FakeClock::new(TICKS_PER_SECOND);
+    clock.oversleep = 0.005;
+    let mut pacer = FramePacer::new(60.0, &clock);
+    clock.advance(0.004);
+    let reads = clock.reads();
+    let wait = pacer.wait_for_frame_end(&mut clock);
+    // The sleep overran the frame, the clock is read once before and once
+    // after it without spinning any further.
+    assert_eq!(clock.reads() - reads, 2);
+    assert!(!wait.missed);
+    assert_close(
+        wait.frame_seconds,
+        0.004 + (1.0 / 60.0 - 0.004 - pacer.sleep_margin) + 0.005,
+    );
+}
+
+#[test]
+fn slow_frames_are_counted_as_missed_without_waiting() {
+    let mut clock = FakeClock::new(TICKS_PER_SECOND);
+    let mut pacer = FramePacer::new(30.0, &clock);
+    clock.advance(0.050);
+    let wait = pacer.wait_for_frame_end(&mut clock);
+    assert!(wait.missed);
+    assert_eq!(clock.slept, 0.0);
+    assert_close(wait.frame_seconds, 0.050);
+    assert_eq!(pacer.missed_frames(), 1);
+
+    // The next frame is measured from where the late one ended.
+    clock.advance(0.010);
+    let wait = pacer.wait_for_frame_end(&mut clock);
+    assert!(!wait.missed);
+    assert_close(wait.work_seconds, 0.010);
+    assert_eq!(pacer.missed_frames(), 1);
+}
+
+#[test]
+fn seconds_elapsed_uses_the_clock_frequency() {
+    let clock = FakeClock::new(1000);
+    assert_close(clock.seconds_elapsed(250, 1250), 1.0);
+    assert_close(clock.seconds_elapsed(1250, 250), 0.0);
+}
+
+#[test]
+fn frame_stats_come_from_the_clock() {
+    let mut clock = FakeClock::new(TICKS_PER_SECOND);
+    clock.ticks_per_read = 0;
+    clock.cycles_per_tick = 3000;
+    let mut timer = FrameTimer::new(&clock);
+    clock.advance(0.020);
+    let frame = timer.frame_end(&clock);
+    assert_close(frame.ms_per_frame(), 20.0);
+    assert_close(frame.frames_per_second(), 50.0);
+    assert_close(frame.mega_cycles_per_frame(), 60.0);
+
+    clock.advance(0.025);
+    assert_close(timer.frame_end(&clock).ms_per_frame(), 25.0);
+}
+
+#[cfg(unix)]
+#[test]
+fn monotonic_clock_moves_forward() {
+    let mut clock = handmade_hero::timing::MonotonicClock;
+    let start = clock.now();
+    clock.sleep(0.002);
+    let end = clock.now();
+    assert!(clock.seconds_elapsed(start, end) >= 0.002);
+}
@@ -0,0 +1,178 @@
+use std::io::Cursor;
+
+use handmade_hero::mixer::{Mixer, SoundId, Voice};
+use handmade_hero::wav::{LoadedSound, WavError, WavWriter};
+use handmade_hero::GameAudioBuffer;
+
+fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+fn format(
+    tag: u16,
+    channels: u16,
+    samples_per_second: u32,
+    bits: u16,
+) -> Vec<u8> {
+    let block_align = channels * bits / 8;
+    let mut body = Vec::new();
+    body.extend_from_slice(&tag.to_le_bytes());
+    body.extend_from_slice(&channels.to_le_bytes());
+    body.extend_from_slice(&samples_per_second.to_le_bytes());
+    body.extend_from_slice(
+        &(samples_per_second * block_align as u32).to_le_bytes(),
+    );
+    body.extend_from_slice(&block_align.to_le_bytes());
+    body.extend_from_slice(&bits.to_le_bytes());
+    body
+}
+
+fn wave(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let body: Vec<u8> = chunks.concat();
+    let mut data = b"RIFF".to_vec();
+    data.extend_from_slice(&(4 + body.len() as u32).to_le_bytes());
+    data.extend_from_slice(b"WAVE");
+    data.extend_from_slice(&body);
+    data
+}
+
+#[test]
+fn written_files_load_back() {
+    let samples = [0, 1, -1, i16::MAX, i16::MIN, 1234, -4321, 7];
+    let mut writer = WavWriter::new(Cursor::new(Vec::new()), 22050, 2).unwrap();
+    writer.write_samples(&samples[..3]).unwrap();
+    writer.write_samples(&samples[3..]).unwrap();
+    let data = writer.finish().unwrap().into_inner();
+    assert_eq!(
+        LoadedSound::from_wav(&data).unwrap(),
+        LoadedSound {
+            samples_per_second: 22050,
+            channels: 2,
+            samples: samples.to_vec().into(),
+        }
+    );
+}
+
+#[test]
+fn eight_bit_mono_is_widened() {
+    let data = wave(&[
+        chunk(b"fmt ", &format(1, 1, 8000, 8)),
+        chunk(b"data", &[0, 128, 255]),
+    ]);
+    let sound = LoadedSound::from_wav(&data).unwrap();
+    assert_eq!(*sound.samples, [i16::MIN, 0, 127 << 8]);
+    assert_eq!(sound.frame_count(), 3);
+    assert_eq!(sound.frame(2), (127 << 8, 127 << 8));
+}
+
+#[test]
+fn unknown_and_odd_sized_chunks_are_skipped() {
+    let mut extensible = format(0xfffe, 2, 44100, 16);
+    extensible.extend_from_slice(&22u16.to_le_bytes());
+    extensible.extend_from_slice(&[0; 6]);
+    extensible.extend_from_slice(&1u16.to_le_bytes());
+    extensible.extend_from_slice(&[0; 14]);
+    let data = wave(&[
+        chunk(b"LIST", b"odd"),
+        chunk(b"fmt ", &extensible),
+        chunk(b"fact", &[0; 4]),
+        // Three bytes, a frame and a half of 16 bit stereo.
+        chunk(b"data", &[1, 0, 2, 0, 3]),
+    ]);
+    let sound = LoadedSound::from_wav(&data).unwrap();
+    assert_eq!(sound.samples_per_second, 44100);
+    assert_eq!(*sound.samples, [1, 2]);
+
+    // Streamed files may leave the data size unpatched.
+    let mut streamed = wave(&[
+        chunk(b"fmt ", &format(1, 1, 8000, 16)),
+        chunk(b"data", &[5, 0, 6, 0]),
+    ]);
+    let size = streamed.len() - 8;
+    streamed[size..size + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(*LoadedSound::from_wav(&streamed).unwrap().samples, [5, 6]);
+}
+
+#[test]
+fn unsupported_files_are_rejected() {
+    let parse = |chunks: &[Vec<u8>]| LoadedSound::from_wav(&wave(chunks));
+    let samples = chunk(b"data", &[0; 8]);
+    assert_eq!(
+        LoadedSound::from_wav(b"RIFF\0\0\0\0AVI "),
+        Err(WavError::NotAWave)
+    );
+    assert_eq!(
+        parse(std::slice::from_ref(&samples)),
+        Err(WavError::MissingChunk("fmt "))
+    );
+    assert_eq!(
+        parse(&[chunk(b"fmt ", &format(1, 1, 8000, 16))]),
+        Err(WavError::MissingChunk("data"))
+    );
+    // IEEE floats.
+    assert_eq!(
+        parse(&[chunk(b"fmt ", &format(3, 1, 8000, 32)), samples.clone()]),
+        Err(WavError::UnsupportedFormat(3))
+    );
+    assert_eq!(
+        parse(&[chunk(b"fmt ", &format(1, 6, 8000, 16)), samples.clone()]),
+        Err(WavError::UnsupportedChannels(6))
+    );
+    assert_eq!(
+        parse(&[chunk(b"fmt ", &format(1, 1, 8000, 24)), samples.clone()]),
+        Err(WavError::UnsupportedBitsPerSample(24))
+    );
+    assert_eq!(
+        parse(&[chunk(b"fmt ", &format(1, 1, 0, 16)), samples.clone()]),
+        Err(WavError::InvalidSampleRate(0))
+    );
+    assert_eq!(
+        parse(&[chunk(b"fmt ", &format(1, 1, 8000, 16)[..12]), samples]),
+        Err(WavError::Truncated)
+    );
+
+    let mut cut = wave(&[chunk(b"fmt ", &format(1, 1, 8000, 16))]);
+    cut.truncate(cut.len() - 2);
+    assert_eq!(LoadedSound::from_wav(&cut), Err(WavError::Truncated));
+}
+
+#[test]
+fn sounds_are_resampled_to_the_output_rate() {
+    // 24kHz, so every frame lasts two frames of output.
+    let sounds = [LoadedSound {
+        samples_per_second: 24000,
+        channels: 1,
+        samples: vec![0, 16384, -16384, 0].into(),
+    }];
+    let mut mixer = Mixer::default();
+    mixer.play(Voice::sound(SoundId(0), 1.0, -1.0)).unwrap();
+    let mut samples = vec![0; 2 * 10];
+    mixer.mix(&mut GameAudioBuffer::new(48000, &mut samples), &sounds, &[]);
+    let left: Vec<i16> = samples.iter().step_by(2).copied().collect();
+    // Halfway between frames is halfway between their samples, the last
+    // frame fades towards silence.
+    assert_eq!(left, [0, 8191, 16383, 0, -16383, -8191, 0, 0, 0, 0]);
+}
+
+#[test]
+fn finish_patches_the_chunk_sizes() {
+    let mut writer = WavWriter::new(Cursor::new(Vec::new()), 8000, 1).unwrap();
+    writer.write_samples(&[1, 2, 3]).unwrap();
+    writer.write_samples(&[]).unwrap();
+    writer.write_samples(&[4, 5]).unwrap();
+    let data = writer.finish().unwrap().into_inner();
+    let size = |offset: usize| {
+        let bytes = &data[offset..offset + 4];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+    assert_eq!(data.len(), 44 + 10);
+    assert_eq!(size(4), 36 + 10);
+    assert_eq!(&data[36..40], b"data");
+    assert_eq!(size(40), 10);
+}